@@ -0,0 +1,106 @@
+use std::{io::Cursor, time::Duration};
+
+use ggez::{audio::SoundData, Context};
+use rodio::Source;
+
+use crate::{
+    config::TrackConfig,
+    tween::{NonTweener, TargetTweener, TweenBox},
+};
+
+fn audio_error(e: impl std::fmt::Display) -> ggez::GameError {
+    ggez::GameError::AudioError(e.to_string())
+}
+
+/// A sound playing on a channel.
+pub struct Track {
+    pub name: String,
+    pub channel: String,
+    pub looping: bool,
+    // Volume of the track before the channel volume, tweened when fading in and out
+    pub fade: TweenBox<f32>,
+    // Set once the track is fading out, it's done when the fade is
+    stopping: bool,
+    // Playback stops when the sink is dropped
+    sink: rodio::Sink,
+}
+
+impl Track {
+    /// Starts playing `data`, fading in over `fade_in` seconds. Looping tracks play until
+    /// `config.loop_start` once and then repeat up to `config.loop_end`.
+    pub fn new(
+        ctx: &mut Context,
+        name: String,
+        channel: String,
+        data: SoundData,
+        config: &TrackConfig,
+        looping: bool,
+        fade_in: f32,
+    ) -> ggez::GameResult<Self> {
+        let sink = rodio::Sink::try_new(ctx.audio_context.device()).map_err(audio_error)?;
+        let decoder = || rodio::Decoder::new(Cursor::new(data.clone())).map_err(audio_error);
+        if looping {
+            let loop_start = Duration::from_secs_f32(config.loop_start);
+            if config.loop_start > 0.0 {
+                sink.append(decoder()?.take_duration(loop_start));
+            }
+            let body = decoder()?.skip_duration(loop_start);
+            match config.loop_end {
+                Some(loop_end) => sink.append(
+                    body.take_duration(Duration::from_secs_f32(loop_end - config.loop_start))
+                        .buffered()
+                        .repeat_infinite(),
+                ),
+                None => sink.append(body.buffered().repeat_infinite()),
+            }
+        } else {
+            sink.append(decoder()?);
+        }
+        // Silent until the first update sets the volume
+        sink.set_volume(0.0);
+        let mut track = Track {
+            name,
+            channel,
+            looping,
+            fade: Box::new(NonTweener::new(1.0)),
+            stopping: false,
+            sink,
+        };
+        if fade_in > 0.0 {
+            track.fade_to(0.0, 1.0, fade_in);
+        }
+        Ok(track)
+    }
+
+    fn fade_to(&mut self, from: f32, to: f32, duration: f32) {
+        self.fade = Box::new(TargetTweener::new(
+            duration,
+            from,
+            move |fade: &mut f32, progress| {
+                *fade = from + (to - from) * progress;
+            },
+        ));
+    }
+
+    /// Fades the track out over `duration` seconds, after which it's done.
+    pub fn stop(&mut self, duration: f32) {
+        let from = *self.fade.get_current();
+        self.stopping = true;
+        if duration > 0.0 {
+            self.fade_to(from, 0.0, duration);
+        } else {
+            self.sink.stop();
+        }
+    }
+
+    /// Whether the track has finished playing or fading out.
+    pub fn is_done(&self) -> bool {
+        self.sink.empty() || (self.stopping && self.fade.is_done())
+    }
+
+    /// Advances the fade, `volume` is the volume of the channel.
+    pub fn update(&mut self, dt: f32, volume: f32) {
+        self.fade.update(dt);
+        self.sink.set_volume(volume * self.fade.get_current());
+    }
+}
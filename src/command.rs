@@ -0,0 +1,387 @@
+use ggez::graphics;
+
+use crate::{containers::filter::Grade, helpers::parse_hex_color};
+
+/// Lines spoken by this speaker are engine commands instead of dialogue, e.g. `@: nvl`.
+pub const COMMAND_SPEAKER: &str = "@";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    // Shows text on a full-screen panel where lines accumulate
+    Nvl,
+    // Shows text in the textbox at the bottom of the screen
+    Adv,
+    // Starts a new NVL page
+    Clear,
+    // Pans and zooms over the background, unset parts of the view stay the same
+    Camera {
+        x: Option<f32>,
+        y: Option<f32>,
+        zoom: Option<f32>,
+        duration: f32,
+    },
+    Shake {
+        strength: f32,
+        duration: f32,
+    },
+    Flash {
+        color: graphics::Color,
+        duration: f32,
+    },
+    // Starts a particle preset, or stops the current one if there's no name
+    Weather {
+        name: Option<String>,
+        // Drawn over the characters instead of behind them
+        front: bool,
+        seed: Option<u64>,
+    },
+    // Plays a sound like `play ... on ...` in a script, with how it loops and fades
+    Play {
+        name: String,
+        channel: String,
+        looping: Option<bool>,
+        fade: Option<f32>,
+    },
+    // Fades out the sound playing on a channel, instantly if the duration is 0
+    Stop {
+        channel: String,
+        duration: f32,
+    },
+    // Color grades the stage, or removes the grade if there's none
+    Filter {
+        grade: Option<Grade>,
+        duration: f32,
+    },
+    // Darkens the edges of the screen, a strength of 0 removes it
+    Vignette {
+        strength: f32,
+        duration: f32,
+        over_ui: bool,
+    },
+    // Fades the screen to a color, or back from it if there's none
+    Fade {
+        color: Option<graphics::Color>,
+        duration: f32,
+        over_ui: bool,
+    },
+}
+
+fn parse_number(s: &str) -> Result<f32, String> {
+    s.parse()
+        .ok()
+        .filter(|n: &f32| n.is_finite())
+        .ok_or_else(|| format!("`{}` is not a number in command", s))
+}
+
+/// Parses a duration like `0.5s` or `0.5`.
+fn parse_seconds(s: &str) -> Option<f32> {
+    s.trim_end_matches('s')
+        .parse()
+        .ok()
+        .filter(|d: &f32| d.is_finite() && *d >= 0.0)
+}
+
+impl Command {
+    /// Parses the content of a command line, e.g. `nvl`, `camera x=0.2 zoom=1.5 2s`,
+    /// `shake 20px 0.5s`, `flash #ffffff`, `weather snow front`, `filter sepia 1s`,
+    /// `vignette 0.8`, `fade black 2s ui`, `play theme on music once 2s` or `stop music 1s`.
+    pub fn parse(v: &str) -> Result<Self, String> {
+        let mut tokens = v.split_whitespace();
+        let name = tokens.next().unwrap_or_default().to_lowercase();
+        let args = tokens.map(|token| token.to_lowercase()).collect::<Vec<_>>();
+        let command = match name.as_str() {
+            "nvl" => Command::Nvl,
+            "adv" => Command::Adv,
+            "clear" | "page" => Command::Clear,
+            "camera" => {
+                let (mut x, mut y, mut zoom, mut duration) = (None, None, None, 0.0);
+                for arg in &args {
+                    if arg == "reset" {
+                        x = Some(0.5);
+                        y = Some(0.5);
+                        zoom = Some(1.0);
+                    } else if let Some(v) = arg.strip_prefix("x=") {
+                        x = Some(parse_number(v)?);
+                    } else if let Some(v) = arg.strip_prefix("y=") {
+                        y = Some(parse_number(v)?);
+                    } else if let Some(v) = arg.strip_prefix("zoom=") {
+                        zoom = Some(parse_number(v)?);
+                    } else if let Some(seconds) = parse_seconds(arg) {
+                        duration = seconds;
+                    } else {
+                        return Err(format!("unexpected `{}` in camera", arg));
+                    }
+                }
+                Command::Camera {
+                    x,
+                    y,
+                    zoom,
+                    duration,
+                }
+            }
+            "shake" => {
+                let (mut strength, mut duration) = (15.0, 0.5);
+                for arg in &args {
+                    if let Some(pixels) = arg.strip_suffix("px") {
+                        strength = parse_number(pixels)?;
+                    } else if let Some(seconds) = parse_seconds(arg) {
+                        duration = seconds;
+                    } else {
+                        return Err(format!("unexpected `{}` in shake", arg));
+                    }
+                }
+                Command::Shake { strength, duration }
+            }
+            "flash" => {
+                let (mut color, mut duration) = (graphics::WHITE, 0.3);
+                for arg in &args {
+                    if arg == "white" {
+                        color = graphics::WHITE;
+                    } else if arg == "black" {
+                        color = graphics::BLACK;
+                    } else if arg.starts_with('#') {
+                        color = parse_hex_color(arg)?;
+                    } else if let Some(seconds) = parse_seconds(arg) {
+                        duration = seconds;
+                    } else {
+                        return Err(format!("unexpected `{}` in flash", arg));
+                    }
+                }
+                Command::Flash { color, duration }
+            }
+            "weather" => {
+                let (mut name, mut front, mut seed) = (None, false, None);
+                for arg in &args {
+                    if arg == "front" {
+                        front = true;
+                    } else if arg == "back" {
+                        front = false;
+                    } else if let Some(v) = arg.strip_prefix("seed=") {
+                        seed = Some(
+                            v.parse()
+                                .map_err(|_| format!("`{}` is not a seed in weather", v))?,
+                        );
+                    } else if name.is_none() {
+                        name = Some(arg.clone());
+                    } else {
+                        return Err(format!("unexpected `{}` in weather", arg));
+                    }
+                }
+                // `weather none` stops it like `weather` on its own
+                let name = name.filter(|name| name != "none" && name != "off");
+                Command::Weather { name, front, seed }
+            }
+            "play" => {
+                // Sound names keep their case since they're file names
+                let name = v.split_whitespace().nth(1).unwrap_or_default().to_owned();
+                let channel = match args.get(1..3) {
+                    Some([on, channel]) if on == "on" && !name.is_empty() => channel.clone(),
+                    _ => return Err("expected `play <sound> on <channel>`".to_owned()),
+                };
+                let (mut looping, mut fade) = (None, None);
+                for arg in &args[3..] {
+                    if arg == "loop" {
+                        looping = Some(true);
+                    } else if arg == "once" {
+                        looping = Some(false);
+                    } else if let Some(seconds) = parse_seconds(arg) {
+                        fade = Some(seconds);
+                    } else {
+                        return Err(format!("unexpected `{}` in play", arg));
+                    }
+                }
+                Command::Play {
+                    name,
+                    channel,
+                    looping,
+                    fade,
+                }
+            }
+            "stop" => {
+                let channel = args.first().cloned().ok_or("expected `stop <channel>`")?;
+                let mut duration = 0.0;
+                for arg in &args[1..] {
+                    match parse_seconds(arg) {
+                        Some(seconds) => duration = seconds,
+                        None => return Err(format!("unexpected `{}` in stop", arg)),
+                    }
+                }
+                Command::Stop { channel, duration }
+            }
+            "filter" => {
+                let (mut grade, mut duration) = (None, 1.0);
+                for arg in &args {
+                    if arg == "none" || arg == "off" {
+                        grade = None;
+                    } else if let Some(v) = Grade::parse(arg) {
+                        grade = Some(v);
+                    } else if let Some(seconds) = parse_seconds(arg) {
+                        duration = seconds;
+                    } else {
+                        return Err(format!("unexpected `{}` in filter", arg));
+                    }
+                }
+                Command::Filter { grade, duration }
+            }
+            "vignette" => {
+                let (mut strength, mut duration, mut over_ui) = (0.6, 1.0, false);
+                for arg in &args {
+                    if arg == "ui" {
+                        over_ui = true;
+                    } else if arg == "none" || arg == "off" {
+                        strength = 0.0;
+                    } else if let Some(seconds) = arg.strip_suffix('s') {
+                        duration = parse_number(seconds)?;
+                    } else {
+                        strength = parse_number(arg)?;
+                    }
+                }
+                Command::Vignette {
+                    strength,
+                    duration,
+                    over_ui,
+                }
+            }
+            "fade" => {
+                let (mut color, mut duration, mut over_ui) = (Some(graphics::BLACK), 1.0, false);
+                for arg in &args {
+                    if arg == "ui" {
+                        over_ui = true;
+                    } else if arg == "none" || arg == "off" {
+                        color = None;
+                    } else if arg == "white" {
+                        color = Some(graphics::WHITE);
+                    } else if arg == "black" {
+                        color = Some(graphics::BLACK);
+                    } else if arg.starts_with('#') {
+                        color = Some(parse_hex_color(arg)?);
+                    } else if let Some(seconds) = parse_seconds(arg) {
+                        duration = seconds;
+                    } else {
+                        return Err(format!("unexpected `{}` in fade", arg));
+                    }
+                }
+                Command::Fade {
+                    color,
+                    duration,
+                    over_ui,
+                }
+            }
+            _ => return Err(format!("unknown command `{}`", v.trim())),
+        };
+        let takes_args = !matches!(command, Command::Nvl | Command::Adv | Command::Clear);
+        if let Some(arg) = args.first().filter(|_| !takes_args) {
+            return Err(format!("unexpected `{}` after `{}`", arg, name));
+        }
+        Ok(command)
+    }
+
+    /// Returns the command `node` runs, None if it isn't a command.
+    pub fn from_node(node: &novelscript::SceneNodeUser) -> Option<Result<Self, String>> {
+        match node {
+            novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+                speaker: Some(speaker),
+                content,
+            }) if speaker == COMMAND_SPEAKER => Some(Command::parse(content)),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn test_command() {
+    assert_eq!(Command::parse("nvl").unwrap(), Command::Nvl);
+    assert_eq!(Command::parse(" ADV ").unwrap(), Command::Adv);
+    assert_eq!(Command::parse("page").unwrap(), Command::Clear);
+    assert!(Command::parse("nvl now").is_err());
+    assert!(Command::parse("").is_err());
+
+    assert_eq!(
+        Command::parse("camera x=0.25 zoom=1.5 2s").unwrap(),
+        Command::Camera {
+            x: Some(0.25),
+            y: None,
+            zoom: Some(1.5),
+            duration: 2.0,
+        }
+    );
+    assert_eq!(
+        Command::parse("shake 20px").unwrap(),
+        Command::Shake {
+            strength: 20.0,
+            duration: 0.5,
+        }
+    );
+    assert_eq!(
+        Command::parse("flash #ff0000 1s").unwrap(),
+        Command::Flash {
+            color: graphics::Color::from_rgb(255, 0, 0),
+            duration: 1.0,
+        }
+    );
+    assert!(Command::parse("camera left").is_err());
+
+    assert_eq!(
+        Command::parse("weather rain front seed=3").unwrap(),
+        Command::Weather {
+            name: Some("rain".to_owned()),
+            front: true,
+            seed: Some(3),
+        }
+    );
+    assert_eq!(
+        Command::parse("weather off").unwrap(),
+        Command::Weather {
+            name: None,
+            front: false,
+            seed: None,
+        }
+    );
+    assert!(Command::parse("weather snow rain").is_err());
+
+    assert_eq!(
+        Command::parse("filter sepia 2s").unwrap(),
+        Command::Filter {
+            grade: Some(Grade::Sepia),
+            duration: 2.0,
+        }
+    );
+    assert_eq!(
+        Command::parse("vignette 0.8 ui").unwrap(),
+        Command::Vignette {
+            strength: 0.8,
+            duration: 1.0,
+            over_ui: true,
+        }
+    );
+    assert_eq!(
+        Command::parse("fade off 0.5").unwrap(),
+        Command::Fade {
+            color: None,
+            duration: 0.5,
+            over_ui: false,
+        }
+    );
+    assert!(Command::parse("filter blue").is_err());
+    assert!(Command::parse("filter sepia infs").is_err());
+    assert!(Command::parse("vignette nan").is_err());
+
+    assert_eq!(
+        Command::parse("play Theme on music once 2s").unwrap(),
+        Command::Play {
+            name: "Theme".to_owned(),
+            channel: "music".to_owned(),
+            looping: Some(false),
+            fade: Some(2.0),
+        }
+    );
+    assert_eq!(
+        Command::parse("stop ambience 1.5s").unwrap(),
+        Command::Stop {
+            channel: "ambience".to_owned(),
+            duration: 1.5,
+        }
+    );
+    assert!(Command::parse("play theme music").is_err());
+    assert!(Command::parse("stop").is_err());
+}
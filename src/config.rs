@@ -1,30 +1,624 @@
-use std::{cell::RefCell, collections::HashMap, path::PathBuf, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
+    path::PathBuf,
+    rc::Rc,
+};
 
 use ggez::{
     filesystem::OpenOptions,
     graphics::{self, Color},
     Context,
 };
+use log::warn;
+
+use crate::{
+    helpers::{parse_hex_color, parse_vec2},
+    placement::Placement,
+};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
+pub struct LayerConfig {
+    pub name: String,
+    // Image shown when the script hasn't chosen one, None to hide the layer
+    pub default: Option<String>,
+    // Offset from the top left of the first layer, in image pixels
+    pub offset: glam::Vec2,
+}
+
+/// A section of characters.ini, the section name is the name used in the script.
+#[derive(Debug, Clone)]
 pub struct CharacterConfig {
+    // Shown instead of the script name
+    pub display_name: Option<String>,
+    // Color of the name
     pub color: Color,
+    pub text_color: Color,
+    pub default_expression: Option<String>,
+    pub default_placement: Option<Placement>,
+    // Folder in /char the images are in, characters sharing a folder are the same on stage
+    pub sprite: Option<String>,
+    // Image shown next to the text when speaking, see `portrait_path`
+    pub portrait: Option<String>,
+    // Sound played as the text is revealed
+    pub voice: Option<String>,
+    // Replaces the /TextBox image when speaking
+    pub textbox: Option<String>,
+    // Drawn bottom to top, empty if the character is a single image per expression
+    pub layers: Vec<LayerConfig>,
 }
 
 impl Default for CharacterConfig {
     fn default() -> Self {
         Self {
+            display_name: None,
             color: graphics::WHITE,
+            text_color: graphics::WHITE,
+            default_expression: None,
+            default_placement: None,
+            sprite: None,
+            portrait: None,
+            voice: None,
+            textbox: None,
+            layers: Vec::new(),
         }
     }
 }
 
+impl Config {
+    /// The folder in /char of the character that says lines as `speaker`.
+    pub fn sprite_name<'a>(&'a self, speaker: &'a str) -> &'a str {
+        self.characters
+            .get(speaker)
+            .and_then(|config| config.sprite.as_deref())
+            .unwrap_or(speaker)
+    }
+
+    /// The layers of the background the scene command calls `name`.
+    pub fn background(&self, name: &str) -> BackgroundConfig {
+        self.backgrounds
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| BackgroundConfig::single(name))
+    }
+
+    /// The name shown for `speaker`.
+    pub fn display_name<'a>(&'a self, speaker: &'a str) -> &'a str {
+        self.characters
+            .get(speaker)
+            .and_then(|config| config.display_name.as_deref())
+            .unwrap_or(speaker)
+    }
+}
+
+impl CharacterConfig {
+    pub fn parse(name: &str, properties: &ini::Properties) -> ggez::GameResult<Self> {
+        let error = |key: &str, e: String| {
+            ggez::GameError::ConfigError(format!("characters.ini [{}] {}: {}", name, key, e))
+        };
+        let color = |key: &str| {
+            properties
+                .get(key)
+                .map(|s| parse_hex_color(s).map_err(|e| error(key, e)))
+                .transpose()
+        };
+        let string = |key: &str| properties.get(key).map(|s| s.to_owned());
+
+        let layers = match properties.get("layers") {
+            Some(layers) => layers
+                .split(',')
+                .map(|layer| {
+                    let layer = layer.trim();
+                    let offset_key = format!("{}_offset", layer);
+                    Ok(LayerConfig {
+                        name: layer.to_owned(),
+                        default: properties
+                            .get(layer)
+                            .filter(|s| !s.is_empty())
+                            .map(|s| s.to_owned()),
+                        offset: properties
+                            .get(&offset_key)
+                            .map(|s| parse_vec2(s).map_err(|e| error(&offset_key, e)))
+                            .transpose()?
+                            .unwrap_or_default(),
+                    })
+                })
+                .collect::<ggez::GameResult<_>>()?,
+            None => Vec::new(),
+        };
+
+        Ok(CharacterConfig {
+            display_name: string("name"),
+            // `color` is the name color from before text colors existed
+            color: color("name_color")?
+                .or(color("color")?)
+                .unwrap_or(graphics::WHITE),
+            text_color: color("text_color")?.unwrap_or(graphics::WHITE),
+            default_expression: string("expression"),
+            default_placement: properties
+                .get("placement")
+                .map(|s| Placement::parse(s).map_err(|e| error("placement", e)))
+                .transpose()?,
+            sprite: string("sprite"),
+            portrait: string("portrait"),
+            voice: string("voice"),
+            textbox: string("textbox"),
+            layers,
+        })
+    }
+
+    pub fn is_layered(&self) -> bool {
+        !self.layers.is_empty()
+    }
+
+    /// The portrait image for the character's current look. `{expression}` in the
+    /// `portrait` setting is replaced by the expression and `{<layer>}` by the image of that layer.
+    pub fn portrait_path(
+        &self,
+        expression: &str,
+        layer_values: &BTreeMap<String, String>,
+    ) -> Option<String> {
+        let mut path = self.portrait.as_ref()?.replace("{expression}", expression);
+        for (layer, value) in layer_values {
+            path = path.replace(&format!("{{{}}}", layer), value);
+        }
+        Some(path)
+    }
+
+    /// Applies an expression like `outfit=winter face=smile` to the layers shown,
+    /// `current` being the layers shown before or None to start from the defaults.
+    /// `layer=none` hides a layer.
+    pub fn layer_values(
+        &self,
+        current: Option<&BTreeMap<String, String>>,
+        expression: &str,
+    ) -> Result<BTreeMap<String, String>, String> {
+        let mut values = match current {
+            Some(current) => current.clone(),
+            None => self
+                .layers
+                .iter()
+                .filter_map(|layer| Some((layer.name.clone(), layer.default.clone()?)))
+                .collect(),
+        };
+        for token in expression.split_whitespace() {
+            let mut split = token.splitn(2, '=');
+            let layer = split.next().unwrap_or_default();
+            let value = split
+                .next()
+                .ok_or_else(|| format!("expected layer=image, got `{}`", token))?;
+            if !self.layers.iter().any(|l| l.name == layer) {
+                return Err(format!("unknown layer `{}`", layer));
+            }
+            if value.is_empty() || value == "none" {
+                values.remove(layer);
+            } else {
+                values.insert(layer.to_owned(), value.to_owned());
+            }
+        }
+        Ok(values)
+    }
+}
+
+/// How a background layer is animated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Animation {
+    // Numbered images in a folder, `0001.png` onwards
+    Frames {
+        count: usize,
+        fps: f32,
+    },
+    // A single image split into a grid of frames, read left to right then top to bottom
+    Sheet {
+        columns: usize,
+        rows: usize,
+        count: usize,
+        fps: f32,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackgroundLayerConfig {
+    // Path in /bg without the extension, the folder of the frames for frame animations
+    pub path: String,
+    // How much the layer follows the camera, layers further away are below 1
+    pub parallax: f32,
+    // Pixels per second the layer moves left by itself, repeating horizontally
+    pub scroll: f32,
+    pub animation: Option<Animation>,
+}
+
+/// A section of backgrounds.ini, the section name is the name used by the scene command.
+/// Backgrounds that aren't in it are a single image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackgroundConfig {
+    // Drawn bottom to top
+    pub layers: Vec<BackgroundLayerConfig>,
+}
+
+impl BackgroundConfig {
+    pub fn single(name: &str) -> Self {
+        BackgroundConfig {
+            layers: vec![BackgroundLayerConfig {
+                path: name.to_owned(),
+                parallax: 1.0,
+                scroll: 0.0,
+                animation: None,
+            }],
+        }
+    }
+
+    /// Layers are listed in `layers`, their settings are prefixed by the layer name,
+    /// e.g. `sky_parallax`. Backgrounds without layers use the settings without a prefix.
+    pub fn parse(name: &str, properties: &ini::Properties) -> ggez::GameResult<Self> {
+        let error = |key: &str, e: String| {
+            ggez::GameError::ConfigError(format!("backgrounds.ini [{}] {}: {}", name, key, e))
+        };
+        let number = |key: &str| {
+            properties
+                .get(key)
+                .map(|s| {
+                    s.trim()
+                        .parse::<f32>()
+                        .map_err(|_| error(key, format!("`{}` is not a number", s)))
+                })
+                .transpose()
+        };
+        let layer = |prefix: &str, path: String| -> ggez::GameResult<BackgroundLayerConfig> {
+            let key = |key: &str| format!("{}{}", prefix, key);
+            let fps = number(&key("fps"))?.unwrap_or(12.0);
+            let frames = number(&key("frames"))?.map(|count| count as usize);
+            if frames == Some(0) {
+                return Err(error(
+                    &key("frames"),
+                    "there must be at least 1 frame".to_owned(),
+                ));
+            }
+            let animation = match properties.get(key("sheet")) {
+                Some(sheet) => {
+                    let mut split = sheet.split('x').map(|n| n.trim().parse::<usize>());
+                    let (columns, rows) = match (split.next(), split.next(), split.next()) {
+                        (Some(Ok(columns)), Some(Ok(rows)), None) if columns * rows > 0 => {
+                            (columns, rows)
+                        }
+                        _ => {
+                            return Err(error(
+                                &key("sheet"),
+                                format!("`{}` is not a grid, expected columnsxrows", sheet),
+                            ))
+                        }
+                    };
+                    Some(Animation::Sheet {
+                        columns,
+                        rows,
+                        count: frames.unwrap_or(columns * rows).min(columns * rows),
+                        fps,
+                    })
+                }
+                None => frames.map(|count| Animation::Frames { count, fps }),
+            };
+            Ok(BackgroundLayerConfig {
+                path,
+                parallax: number(&key("parallax"))?.unwrap_or(1.0),
+                scroll: number(&key("scroll"))?.unwrap_or(0.0),
+                animation,
+            })
+        };
+
+        let layers = match properties.get("layers") {
+            Some(layers) => layers
+                .split(',')
+                .map(|l| {
+                    let l = l.trim();
+                    layer(&format!("{}_", l), format!("{}/{}", name, l))
+                })
+                .collect::<ggez::GameResult<_>>()?,
+            None => vec![layer("", name.to_owned())?],
+        };
+        Ok(BackgroundConfig { layers })
+    }
+}
+
+// Most particles a preset can have on screen at once
+const MAX_PARTICLES: usize = 2000;
+
+/// A kind of weather or particle effect, built in or a section of particles.ini.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParticlePreset {
+    // Drawn for every particle, a soft dot if None
+    pub image: Option<String>,
+    pub color: Color,
+    // Particles on screen at once
+    pub count: usize,
+    // Smallest and largest size in pixels
+    pub size: glam::Vec2,
+    // Average velocity in pixels per second
+    pub velocity: glam::Vec2,
+    // How much the velocity of each particle differs from the average, at most
+    pub spread: glam::Vec2,
+    // Pixels the particles sway from side to side
+    pub sway: f32,
+    // Particles are stretched this many times their size along their velocity, for rain
+    pub stretch: f32,
+}
+
+impl ParticlePreset {
+    pub fn builtin(name: &str) -> Option<Self> {
+        let preset = ParticlePreset {
+            image: None,
+            color: graphics::WHITE,
+            count: 150,
+            size: glam::Vec2::new(3.0, 8.0),
+            velocity: glam::Vec2::new(10.0, 60.0),
+            spread: glam::Vec2::new(20.0, 20.0),
+            sway: 20.0,
+            stretch: 1.0,
+        };
+        Some(match name {
+            "snow" => preset,
+            "rain" => ParticlePreset {
+                color: Color::new(0.7, 0.75, 0.9, 0.6),
+                count: 300,
+                size: glam::Vec2::new(2.0, 3.0),
+                velocity: glam::Vec2::new(-100.0, 900.0),
+                spread: glam::Vec2::new(10.0, 100.0),
+                sway: 0.0,
+                stretch: 12.0,
+                ..preset
+            },
+            "petals" => ParticlePreset {
+                color: Color::from_rgb(255, 183, 197),
+                count: 40,
+                size: glam::Vec2::new(8.0, 14.0),
+                velocity: glam::Vec2::new(40.0, 50.0),
+                sway: 40.0,
+                ..preset
+            },
+            "dust" => ParticlePreset {
+                color: Color::new(1.0, 1.0, 0.9, 0.4),
+                count: 60,
+                size: glam::Vec2::new(2.0, 5.0),
+                velocity: glam::Vec2::new(5.0, -5.0),
+                spread: glam::Vec2::new(10.0, 10.0),
+                sway: 10.0,
+                ..preset
+            },
+            _ => return None,
+        })
+    }
+
+    /// Settings that aren't in the section are taken from the built in preset of the same name,
+    /// or from snow for new presets.
+    pub fn parse(name: &str, properties: &ini::Properties) -> ggez::GameResult<Self> {
+        let error = |key: &str, e: String| {
+            ggez::GameError::ConfigError(format!("particles.ini [{}] {}: {}", name, key, e))
+        };
+        let vec2 = |key: &str| {
+            properties
+                .get(key)
+                .map(|s| parse_vec2(s).map_err(|e| error(key, e)))
+                .transpose()
+        };
+        let number = |key: &str| {
+            properties
+                .get(key)
+                .map(|s| {
+                    s.trim()
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|n| n.is_finite())
+                        .ok_or_else(|| error(key, format!("`{}` is not a number", s)))
+                })
+                .transpose()
+        };
+        let base = ParticlePreset::builtin(name)
+            .or_else(|| ParticlePreset::builtin("snow"))
+            .unwrap();
+        let count = match number("count")? {
+            Some(count) if count < 0.0 => {
+                return Err(error("count", format!("`{}` is negative", count)))
+            }
+            // Too many would bring the game to a crawl
+            Some(count) => (count as usize).min(MAX_PARTICLES),
+            None => base.count,
+        };
+        let mut color = properties
+            .get("color")
+            .map(|s| parse_hex_color(s).map_err(|e| error("color", e)))
+            .transpose()?
+            .unwrap_or(base.color);
+        if let Some(alpha) = number("alpha")? {
+            color.a = alpha;
+        }
+        Ok(ParticlePreset {
+            image: properties.get("image").map(|s| s.to_owned()).or(base.image),
+            color,
+            count,
+            size: vec2("size")?.unwrap_or(base.size),
+            velocity: vec2("velocity")?.unwrap_or(base.velocity),
+            spread: vec2("spread")?.unwrap_or(base.spread),
+            sway: number("sway")?.unwrap_or(base.sway),
+            stretch: number("stretch")?.unwrap_or(base.stretch),
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct UIConfig {
     pub title: String,
     pub button_color: Color,
     pub button_pressed_color: Color,
     pub button_highlight_color: Color,
+    pub bold_font: Option<graphics::Font>,
+    pub italic_font: Option<graphics::Font>,
+}
+
+/// The optional `[Stage]` section of engine.ini.
+#[derive(Debug)]
+pub struct StageConfig {
+    // Seconds to crossfade between expressions
+    pub expression_fade: f32,
+    // Dims the characters that aren't speaking
+    pub highlight_speaker: bool,
+}
+
+impl Default for StageConfig {
+    fn default() -> Self {
+        Self {
+            expression_fade: 0.25,
+            highlight_speaker: false,
+        }
+    }
+}
+
+/// Channels every game has, in the order their volume sliders are shown.
+pub const BUILTIN_CHANNELS: [&str; 4] = ["music", "sfx", "voice", "ambience"];
+
+/// How many channels fit in the options window, two columns of volume sliders with master.
+pub const MAX_CHANNELS: usize = 15;
+
+/// The optional `[Audio]` section of engine.ini.
+#[derive(Debug)]
+pub struct AudioConfig {
+    // The built in channels followed by the ones declared in `channels`
+    pub channels: Vec<String>,
+    // Sounds on these channels loop unless audio.ini says otherwise
+    pub looping: Vec<String>,
+    // Seconds a looping sound takes to fade into the one it replaces
+    pub crossfade: f32,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            channels: BUILTIN_CHANNELS.iter().map(|&s| s.to_owned()).collect(),
+            looping: vec!["music".to_owned(), "ambience".to_owned()],
+            crossfade: 1.0,
+        }
+    }
+}
+
+/// Splits a comma separated list of channel names, e.g. `crowd, rain`.
+fn parse_channel_list(key: &str, list: &str) -> ggez::GameResult<Vec<String>> {
+    let mut channels = Vec::new();
+    for channel in list.split(',') {
+        let channel = channel.trim().to_lowercase();
+        if channel == "master" || channel.contains(char::is_whitespace) {
+            return Err(ggez::GameError::ConfigError(format!(
+                "engine.ini [Audio] {}: `{}` can't be used as a channel name",
+                key, channel
+            )));
+        }
+        if !channel.is_empty() && !channels.contains(&channel) {
+            channels.push(channel);
+        }
+    }
+    Ok(channels)
+}
+
+impl AudioConfig {
+    /// `channels` adds channels to the built in ones, `looping` replaces the channels that loop.
+    pub fn parse(properties: &ini::Properties) -> ggez::GameResult<Self> {
+        let mut config = Self::default();
+        if let Some(list) = properties.get("channels") {
+            for channel in parse_channel_list("channels", list)? {
+                if !config.channels.contains(&channel) {
+                    config.channels.push(channel);
+                }
+            }
+            if config.channels.len() > MAX_CHANNELS {
+                return Err(ggez::GameError::ConfigError(format!(
+                    "engine.ini [Audio] channels: at most {} channels fit in the options, including the {} built in ones",
+                    MAX_CHANNELS,
+                    BUILTIN_CHANNELS.len()
+                )));
+            }
+        }
+        if let Some(list) = properties.get("looping") {
+            config.looping = parse_channel_list("looping", list)?;
+        }
+        if let Some(crossfade) = properties.get("crossfade") {
+            config.crossfade = crossfade
+                .trim_end_matches('s')
+                .parse()
+                .ok()
+                .filter(|crossfade: &f32| *crossfade >= 0.0)
+                .ok_or_else(|| {
+                    ggez::GameError::ConfigError(format!(
+                        "engine.ini [Audio] crossfade: `{}` is not a duration",
+                        crossfade
+                    ))
+                })?;
+        }
+        Ok(config)
+    }
+
+    pub fn has_channel(&self, channel: &str) -> bool {
+        self.channels.iter().any(|c| c == channel)
+    }
+
+    /// Name of the volume slider of `channel` in the options.
+    pub fn label(channel: &str) -> String {
+        match channel {
+            "music" => "BGM".to_owned(),
+            "sfx" => "SFX".to_owned(),
+            _ => {
+                let mut chars = channel.chars();
+                chars
+                    .next()
+                    .map(|c| c.to_uppercase().chain(chars).collect())
+                    .unwrap_or_default()
+            }
+        }
+    }
+}
+
+/// A section of audio.ini, named after the sound.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TrackConfig {
+    // Overrides whether the channel loops
+    pub looping: Option<bool>,
+    // Seconds into the sound where the loop starts, everything before is only played once
+    pub loop_start: f32,
+    // Seconds into the sound where it goes back to `loop_start`, the end if None
+    pub loop_end: Option<f32>,
+}
+
+impl TrackConfig {
+    pub fn parse(name: &str, properties: &ini::Properties) -> ggez::GameResult<Self> {
+        let error = |key: &str, msg: String| {
+            ggez::GameError::ConfigError(format!("audio.ini [{}] {}: {}", name, key, msg))
+        };
+        let seconds = |key: &str| -> ggez::GameResult<Option<f32>> {
+            properties
+                .get(key)
+                .map(|v| {
+                    v.trim_end_matches('s')
+                        .parse()
+                        .ok()
+                        .filter(|seconds: &f32| seconds.is_finite() && *seconds >= 0.0)
+                        .ok_or_else(|| error(key, format!("`{}` is not a duration", v)))
+                })
+                .transpose()
+        };
+        let config = TrackConfig {
+            looping: properties
+                .get("loop")
+                .map(|v| {
+                    v.parse()
+                        .map_err(|_| error("loop", format!("`{}` is not true or false", v)))
+                })
+                .transpose()?,
+            loop_start: seconds("loop_start")?.unwrap_or(0.0),
+            loop_end: seconds("loop_end")?,
+        };
+        if let Some(loop_end) = config.loop_end {
+            if loop_end <= config.loop_start {
+                return Err(error("loop_end", "must be after loop_start".to_owned()));
+            }
+        }
+        Ok(config)
+    }
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -33,23 +627,96 @@ pub struct Channels(pub HashMap<String, f32>);
 impl Default for Channels {
     fn default() -> Self {
         Self(
-            [("sfx".into(), 1.0), ("music".into(), 1.0)]
+            BUILTIN_CHANNELS
                 .iter()
-                .cloned()
+                .map(|&channel| (channel.to_owned(), 1.0))
                 .collect(),
         )
     }
 }
 
+impl Channels {
+    /// Channels missing from config.json are at full volume.
+    pub fn volume(&self, channel: &str) -> f32 {
+        self.0.get(channel).copied().unwrap_or(1.0)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum SkipMode {
+    ReadOnly,
+    All,
+}
+
+impl Default for SkipMode {
+    fn default() -> Self {
+        SkipMode::ReadOnly
+    }
+}
+
+pub const TEXT_SPEED_RANGE: (f32, f32) = (10.0, 200.0);
+pub const AUTO_DELAY_RANGE: (f32, f32) = (0.2, 5.0);
+pub const SKIP_DELAY_RANGE: (f32, f32) = (0.02, 0.5);
+
+/// Maps a value inside `range` to a slider progress between 0 and 1.
+pub fn range_to_progress(value: f32, range: (f32, f32)) -> f32 {
+    ((value - range.0) / (range.1 - range.0)).max(0.0).min(1.0)
+}
+
+/// Maps a slider progress between 0 and 1 to a value inside `range`.
+pub fn progress_to_range(progress: f32, range: (f32, f32)) -> f32 {
+    range.0 + (range.1 - range.0) * progress
+}
+
+fn default_text_speed() -> f32 {
+    75.0
+}
+
+fn default_auto_delay() -> f32 {
+    1.0
+}
+
+fn default_skip_delay() -> f32 {
+    0.1
+}
+
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct UserConfig {
     #[serde(default)]
     pub master_volume: f32,
     #[serde(default)]
     pub channel_volumes: Channels,
+    #[serde(default)]
+    pub skip_mode: SkipMode,
+    // Characters per second
+    #[serde(default = "default_text_speed")]
+    pub text_speed: f32,
+    // Seconds to wait after a line has finished, scaled by the length of the line
+    #[serde(default = "default_auto_delay")]
+    pub auto_delay: f32,
+    // Seconds per line
+    #[serde(default = "default_skip_delay")]
+    pub skip_delay: f32,
 }
 
 impl UserConfig {
+    /// Keeps the settings the options window has sliders for inside their ranges,
+    /// config.json could have been edited by hand or written by another version.
+    pub fn sanitize(self) -> Self {
+        let clamp = |value: f32, range: (f32, f32)| value.max(range.0).min(range.1);
+        Self {
+            text_speed: clamp(self.text_speed, TEXT_SPEED_RANGE),
+            auto_delay: clamp(self.auto_delay, AUTO_DELAY_RANGE),
+            skip_delay: clamp(self.skip_delay, SKIP_DELAY_RANGE),
+            ..self
+        }
+    }
+
+    /// How long auto mode waits on a finished line, longer lines stay on screen longer.
+    pub fn auto_delay_for(&self, char_count: usize) -> f32 {
+        self.auto_delay * (0.5 + char_count as f32 / 60.0)
+    }
+
     pub fn update_data(&self, ctx: &mut Context, short_game_name: &str) {
         let path = PathBuf::new()
             .join("/")
@@ -79,15 +746,276 @@ impl Default for UserConfig {
         Self {
             master_volume: 0.5,
             channel_volumes: Channels::default(),
+            skip_mode: SkipMode::default(),
+            text_speed: default_text_speed(),
+            auto_delay: default_auto_delay(),
+            skip_delay: default_skip_delay(),
+        }
+    }
+}
+
+/// Every line that has been shown in any playthrough.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct ReadHistory {
+    pub lines: HashSet<String>,
+    // Whether lines were read since read.json was last written
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl ReadHistory {
+    fn path(short_game_name: &str) -> PathBuf {
+        PathBuf::new()
+            .join("/")
+            .join(short_game_name)
+            .join("read.json")
+    }
+
+    pub fn load(ctx: &mut Context, short_game_name: &str) -> Self {
+        let path = Self::path(short_game_name);
+        if ggez::filesystem::exists(ctx, &path) {
+            let file = ggez::filesystem::open(ctx, path).unwrap();
+            serde_json::from_reader(file).unwrap_or_default()
+        } else {
+            Self::default()
         }
     }
+
+    /// Marks the line as read, returns whether it had been read before.
+    /// It's only written to read.json by [`flush`](Self::flush).
+    pub fn mark_read(&mut self, key: String) -> bool {
+        if self.lines.insert(key) {
+            self.dirty = true;
+            false
+        } else {
+            true
+        }
+    }
+
+    /// Writes read.json if lines were read since it was last written.
+    pub fn flush(&mut self, ctx: &mut Context, short_game_name: &str) {
+        if !self.dirty {
+            return;
+        }
+        match self.update_data(ctx, short_game_name) {
+            Ok(()) => self.dirty = false,
+            Err(e) => warn!("Unable to write the read history: {}", e),
+        }
+    }
+
+    fn update_data(&self, ctx: &mut Context, short_game_name: &str) -> ggez::GameResult {
+        let path = Self::path(short_game_name);
+        if !ggez::filesystem::exists(ctx, path.parent().unwrap()) {
+            ggez::filesystem::create_dir(ctx, path.parent().unwrap())?;
+        }
+        let file = ggez::filesystem::create(ctx, path)?;
+        serde_json::to_writer(file, self)
+            .map_err(|e| ggez::GameError::ResourceLoadError(e.to_string()))
+    }
+}
+
+#[derive(Debug)]
+pub struct GlossaryEntry {
+    pub name: String,
+    pub definition: String,
 }
 
 #[derive(Debug)]
 pub struct Config {
     pub short_game_name: String,
     pub characters: HashMap<String, CharacterConfig>,
+    pub backgrounds: HashMap<String, BackgroundConfig>,
+    // Built in presets along with the ones in particles.ini
+    pub particles: HashMap<String, ParticlePreset>,
     pub credits: String,
+    pub glossary: HashMap<String, GlossaryEntry>,
     pub ui: UIConfig,
+    pub stage: StageConfig,
+    pub audio: AudioConfig,
+    // Loop settings of the sounds in audio.ini
+    pub tracks: HashMap<String, TrackConfig>,
     pub user: Rc<RefCell<UserConfig>>,
+    pub read: Rc<RefCell<ReadHistory>>,
+}
+
+#[test]
+fn test_layer_values() {
+    let config = CharacterConfig {
+        layers: vec![
+            LayerConfig {
+                name: "body".to_owned(),
+                default: Some("base".to_owned()),
+                offset: glam::Vec2::zero(),
+            },
+            LayerConfig {
+                name: "face".to_owned(),
+                default: Some("normal".to_owned()),
+                offset: glam::Vec2::new(120.0, 80.0),
+            },
+            LayerConfig {
+                name: "blush".to_owned(),
+                default: None,
+                offset: glam::Vec2::zero(),
+            },
+        ],
+        ..CharacterConfig::default()
+    };
+    let values = config.layer_values(None, "face=smile blush=on").unwrap();
+    assert_eq!(values["body"], "base");
+    assert_eq!(values["face"], "smile");
+    assert_eq!(values["blush"], "on");
+    let values = config.layer_values(Some(&values), "blush=none").unwrap();
+    assert_eq!(values.get("blush"), None);
+    assert_eq!(values["face"], "smile");
+    assert!(config.layer_values(None, "hat=red").is_err());
+    assert!(config.layer_values(None, "smile").is_err());
+}
+
+#[test]
+fn test_portrait_path() {
+    let mut config = CharacterConfig {
+        portrait: Some("/portrait/Yukio/{outfit}_{face}".to_owned()),
+        ..CharacterConfig::default()
+    };
+    let layers = [("outfit", "winter"), ("face", "smile")]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    assert_eq!(
+        config.portrait_path("", &layers).unwrap(),
+        "/portrait/Yukio/winter_smile"
+    );
+    config.portrait = Some("/portrait/{expression}".to_owned());
+    assert_eq!(
+        config.portrait_path("happy", &BTreeMap::new()).unwrap(),
+        "/portrait/happy"
+    );
+}
+
+#[test]
+fn test_background_config() {
+    let ini = ini::Ini::load_from_str(
+        "[Bridge]\nlayers = sky, river\nsky_parallax = 0.2\nsky_scroll = 15\n\
+         river_sheet = 4x2\nriver_frames = 6\n\
+         [Rain]\nframes = 12\nfps = 24\n\
+         [Broken]\nsheet = 4\n",
+    )
+    .unwrap();
+    let bridge = BackgroundConfig::parse("Bridge", ini.section(Some("Bridge")).unwrap()).unwrap();
+    assert_eq!(bridge.layers.len(), 2);
+    assert_eq!(bridge.layers[0].path, "Bridge/sky");
+    assert_eq!(bridge.layers[0].parallax, 0.2);
+    assert_eq!(bridge.layers[0].scroll, 15.0);
+    assert_eq!(bridge.layers[0].animation, None);
+    assert_eq!(
+        bridge.layers[1].animation,
+        Some(Animation::Sheet {
+            columns: 4,
+            rows: 2,
+            count: 6,
+            fps: 12.0,
+        })
+    );
+    let rain = BackgroundConfig::parse("Rain", ini.section(Some("Rain")).unwrap()).unwrap();
+    assert_eq!(rain.layers[0].path, "Rain");
+    assert_eq!(
+        rain.layers[0].animation,
+        Some(Animation::Frames {
+            count: 12,
+            fps: 24.0,
+        })
+    );
+    assert!(BackgroundConfig::parse("Broken", ini.section(Some("Broken")).unwrap()).is_err());
+}
+
+#[test]
+fn test_particle_preset() {
+    let ini = ini::Ini::load_from_str(
+        "[rain]\ncount = 500\n[embers]\ncolor = ff8000\nalpha = 0.5\nvelocity = 0, -40\n",
+    )
+    .unwrap();
+    let rain = ParticlePreset::parse("rain", ini.section(Some("rain")).unwrap()).unwrap();
+    assert_eq!(rain.count, 500);
+    assert_eq!(
+        rain.stretch,
+        ParticlePreset::builtin("rain").unwrap().stretch
+    );
+    let embers = ParticlePreset::parse("embers", ini.section(Some("embers")).unwrap()).unwrap();
+    assert_eq!(embers.color, Color::new(1.0, 128.0 / 255.0, 0.0, 0.5));
+    assert_eq!(embers.velocity, glam::Vec2::new(0.0, -40.0));
+    assert_eq!(embers.sway, ParticlePreset::builtin("snow").unwrap().sway);
+
+    let ini =
+        ini::Ini::load_from_str("[a]\ncount = -5\n[b]\ncount = nan\n[c]\ncount = 1e9\n").unwrap();
+    assert!(ParticlePreset::parse("a", ini.section(Some("a")).unwrap()).is_err());
+    assert!(ParticlePreset::parse("b", ini.section(Some("b")).unwrap()).is_err());
+    let c = ParticlePreset::parse("c", ini.section(Some("c")).unwrap()).unwrap();
+    assert_eq!(c.count, MAX_PARTICLES);
+}
+
+#[test]
+fn test_audio_config() {
+    let ini = ini::Ini::load_from_str("[Audio]\nchannels = Crowd, voice, rain\n").unwrap();
+    let config = AudioConfig::parse(ini.section(Some("Audio")).unwrap()).unwrap();
+    assert_eq!(
+        config.channels,
+        vec!["music", "sfx", "voice", "ambience", "crowd", "rain"]
+    );
+    assert_eq!(AudioConfig::label("crowd"), "Crowd");
+    assert_eq!(AudioConfig::label("music"), "BGM");
+
+    let ini = ini::Ini::load_from_str("[Audio]\nchannels = master\n").unwrap();
+    assert!(AudioConfig::parse(ini.section(Some("Audio")).unwrap()).is_err());
+
+    let list = (0..MAX_CHANNELS)
+        .map(|n| format!("extra{}", n))
+        .collect::<Vec<_>>();
+    let ini =
+        ini::Ini::load_from_str(&format!("[Audio]\nchannels = {}\n", list.join(", "))).unwrap();
+    assert!(AudioConfig::parse(ini.section(Some("Audio")).unwrap()).is_err());
+
+    assert_eq!(config.looping, vec!["music", "ambience"]);
+
+    let ini = ini::Ini::load_from_str("[Audio]\nlooping = music, Rain\ncrossfade = 2s\n").unwrap();
+    let config = AudioConfig::parse(ini.section(Some("Audio")).unwrap()).unwrap();
+    assert_eq!(config.looping, vec!["music", "rain"]);
+    assert_eq!(config.crossfade, 2.0);
+
+    let mut volumes = Channels::default();
+    volumes.0.remove("music");
+    assert_eq!(volumes.volume("music"), 1.0);
+    assert_eq!(volumes.volume("crowd"), 1.0);
+}
+
+#[test]
+fn test_track_config() {
+    let ini = ini::Ini::load_from_str(
+        "[bgm]\nloop_start = 4.5\nloop_end = 62s\n[jingle]\nloop = false\n[bad]\nloop_start = 10\nloop_end = 5\n[endless]\nloop_end = inf\n",
+    )
+    .unwrap();
+    let bgm = TrackConfig::parse("bgm", ini.section(Some("bgm")).unwrap()).unwrap();
+    assert_eq!(
+        bgm,
+        TrackConfig {
+            looping: None,
+            loop_start: 4.5,
+            loop_end: Some(62.0),
+        }
+    );
+    let jingle = TrackConfig::parse("jingle", ini.section(Some("jingle")).unwrap()).unwrap();
+    assert_eq!(jingle.looping, Some(false));
+    assert!(TrackConfig::parse("bad", ini.section(Some("bad")).unwrap()).is_err());
+    assert!(TrackConfig::parse("endless", ini.section(Some("endless")).unwrap()).is_err());
+}
+
+#[test]
+fn test_user_config_sanitize() {
+    let config: UserConfig =
+        serde_json::from_str(r#"{ "text_speed": 0, "auto_delay": 100, "skip_delay": 0.25 }"#)
+            .unwrap();
+    let config = config.sanitize();
+    assert_eq!(config.text_speed, TEXT_SPEED_RANGE.0);
+    assert_eq!(config.auto_delay, AUTO_DELAY_RANGE.1);
+    assert_eq!(config.skip_delay, 0.25);
 }
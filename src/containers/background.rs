@@ -1,55 +1,127 @@
 use ggez::{
-    graphics::{self, DrawParam, Drawable},
+    graphics::{self, DrawParam, Rect},
     mint, Context,
 };
 
 use crate::{states::game::Background, tween::TransitionTweenBox};
 
+use super::{camera::CameraView, filter::StageFilter, Update};
+
 pub struct BackgroundContainer {
     pub current: TransitionTweenBox<Background>,
 }
 
-impl Drawable for BackgroundContainer {
-    fn draw(&self, ctx: &mut Context, param: DrawParam) -> ggez::GameResult {
+fn draw_background(
+    ctx: &mut Context,
+    background: &Background,
+    view: &CameraView,
+    filter: &StageFilter,
+    alpha: f32,
+) -> ggez::GameResult {
+    let Background {
+        layers,
+        time,
+        fade,
+        offset,
+        clip,
+        brightness,
+        reveal,
+        ..
+    } = background;
+    let color = graphics::Color::new(*brightness, *brightness, *brightness, fade * alpha);
+    for layer in layers {
+        let (path, image, frame) = layer.frame(*time);
+        // The rule image steps already contain the alpha of the reveal and the grade,
+        // the next step fades in over the current one so the wipe doesn't jump between them
+        let (steps, color) = match reveal {
+            Some(steps) if !steps.is_empty() => {
+                let last = steps.len() - 1;
+                let position = (fade * last as f32).max(0.0).min(last as f32);
+                let step = (position.floor() as usize).min(last);
+                let next = (step + 1).min(last);
+                let color = graphics::Color { a: alpha, ..color };
+                (
+                    Some((&steps[step], &steps[next], position - step as f32)),
+                    color,
+                )
+            }
+            _ => (None, color),
+        };
+        let size = layer.size();
+        let (dest, scale) = view.parallax(layer.parallax).place(size);
+        let drawn = size * scale;
+        let src = Rect::new(
+            frame.x + clip.x * frame.w,
+            frame.y + clip.y * frame.h,
+            clip.w * frame.w,
+            clip.h * frame.h,
+        );
+        // Scrolling layers are drawn twice to repeat
+        let scrolled = -(time * layer.scroll).rem_euclid(drawn.x);
+        let repeats = if layer.scroll == 0.0 { 1 } else { 2 };
+        for n in 0..repeats {
+            let x = scrolled + n as f32 * drawn.x;
+            let param = graphics::DrawParam::new()
+                .src(src)
+                .dest(mint::Point2 {
+                    x: dest.x + x + offset.x + clip.x * drawn.x,
+                    y: dest.y + offset.y + clip.y * drawn.y,
+                })
+                .scale(mint::Vector2 { x: scale, y: scale })
+                .color(color);
+            match steps {
+                Some((step, next, progress)) => {
+                    for &(step, step_alpha) in &[(step, 1.0), (next, progress)] {
+                        if step_alpha <= 0.0 {
+                            continue;
+                        }
+                        // The steps can be smaller than the image
+                        graphics::draw(
+                            ctx,
+                            step,
+                            param
+                                .color(graphics::Color {
+                                    a: color.a * step_alpha,
+                                    ..color
+                                })
+                                .scale(mint::Vector2 {
+                                    x: scale * size.x / step.width() as f32,
+                                    y: scale * size.y / step.height() as f32,
+                                }),
+                        )?;
+                    }
+                }
+                None => filter.draw_image(ctx, path, image, param)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+impl BackgroundContainer {
+    pub fn draw(
+        &self,
+        ctx: &mut Context,
+        param: DrawParam,
+        view: &CameraView,
+        filter: &StageFilter,
+    ) -> ggez::GameResult {
         let background = self.current.get_current();
-        if let Some(Background {
-            name: _,
-            fade,
-            image,
-        }) = &background.0
-        {
-            graphics::draw(
-                ctx,
-                image,
-                graphics::DrawParam::new()
-                    .scale(mint::Vector2 {
-                        x: crate::helpers::target_size().x / image.width() as f32,
-                        y: crate::helpers::target_size().y / image.height() as f32,
-                    })
-                    .color(graphics::Color {
-                        a: *fade * param.color.a,
-                        ..graphics::WHITE
-                    }),
-            )?;
+        if let Some(prev) = &background.0 {
+            draw_background(ctx, prev, view, filter, param.color.a)?;
         }
-        let Background {
-            name: _,
-            fade,
-            image,
-        } = &background.1;
-        graphics::draw(
-            ctx,
-            image,
-            graphics::DrawParam::new()
-                .scale(mint::Vector2 {
-                    x: crate::helpers::target_size().x / image.width() as f32,
-                    y: crate::helpers::target_size().y / image.height() as f32,
-                })
-                .color(graphics::Color {
-                    a: *fade * param.color.a,
-                    ..graphics::WHITE
-                }),
-        )?;
+        draw_background(ctx, &background.1, view, filter, param.color.a)?;
         Ok(())
     }
 }
+
+impl Update for BackgroundContainer {
+    fn update(&mut self, dt: f32) {
+        self.current.update(dt);
+        let (prev, current) = self.current.get_current_mut();
+        if let Some(prev) = prev {
+            prev.time += dt;
+        }
+        current.time += dt;
+    }
+}
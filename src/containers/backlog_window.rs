@@ -0,0 +1,52 @@
+use ggez::graphics::{DrawParam, Drawable, Mesh, Rect, Text};
+
+use super::button::Button;
+
+pub struct BacklogWindow {
+    pub panel: Mesh,
+    pub exit_button: Button,
+    pub entries: Vec<Text>,
+    pub bounds: Rect,
+    // How many entries have been scrolled up from the latest one
+    pub scroll: usize,
+}
+
+impl BacklogWindow {
+    pub fn scroll_up(&mut self) {
+        if self.scroll + 1 < self.entries.len() {
+            self.scroll += 1;
+        }
+    }
+
+    /// Returns false if it's already scrolled to the latest entry.
+    pub fn scroll_down(&mut self) -> bool {
+        if self.scroll == 0 {
+            false
+        } else {
+            self.scroll -= 1;
+            true
+        }
+    }
+}
+
+impl Drawable for BacklogWindow {
+    fn draw(&self, ctx: &mut ggez::Context, param: DrawParam) -> ggez::GameResult {
+        self.panel.draw(ctx, param)?;
+
+        // Laid out from the bottom so the latest entry is always visible
+        let mut y = self.bounds.bottom();
+        for text in self.entries.iter().rev().skip(self.scroll) {
+            y -= text.height(ctx) as f32 + 10.0;
+            if y < self.bounds.top() {
+                break;
+            }
+            text.draw(
+                ctx,
+                DrawParam::new().dest([self.bounds.x, y]).color(param.color),
+            )?;
+        }
+
+        self.exit_button.draw(ctx, param)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use ggez::{
+    graphics::{self, DrawMode, DrawParam, Drawable, FillOptions, Mesh, Rect},
+    Context,
+};
+
+use crate::{
+    transition::smoothstep,
+    tween::{NonTweener, TargetTweener, TweenBox},
+};
+
+use super::Update;
+
+/// The part of the background that is shown.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CameraView {
+    // Point of the background at the center of the screen, as fractions of the image
+    pub x: f32,
+    pub y: f32,
+    // 1 when the background just covers the screen
+    pub zoom: f32,
+}
+
+impl Default for CameraView {
+    fn default() -> Self {
+        Self {
+            x: 0.5,
+            y: 0.5,
+            zoom: 1.0,
+        }
+    }
+}
+
+impl CameraView {
+    /// Where an image of `image_size` is drawn and how much it's scaled by to show this view.
+    /// The image always covers the screen, so the view stops at its edges.
+    pub fn place(&self, image_size: glam::Vec2) -> (glam::Vec2, f32) {
+        let screen = crate::helpers::target_size();
+        let cover = (screen.x / image_size.x).max(screen.y / image_size.y);
+        let scale = cover * self.zoom.max(1.0);
+        let size = image_size * scale;
+        let focus = glam::Vec2::new(self.x * size.x, self.y * size.y);
+        let dest = screen / 2.0 - focus;
+        (
+            glam::Vec2::new(
+                dest.x.max(screen.x - size.x).min(0.0),
+                dest.y.max(screen.y - size.y).min(0.0),
+            ),
+            scale,
+        )
+    }
+
+    /// The view of a background layer that moves `parallax` times as much as the camera.
+    pub fn parallax(&self, parallax: f32) -> CameraView {
+        CameraView::default().lerp(self, parallax)
+    }
+
+    fn lerp(&self, to: &CameraView, progress: f32) -> CameraView {
+        CameraView {
+            x: self.x + (to.x - self.x) * progress,
+            y: self.y + (to.y - self.y) * progress,
+            zoom: self.zoom + (to.zoom - self.zoom) * progress,
+        }
+    }
+}
+
+pub struct Camera {
+    pub view: TweenBox<CameraView>,
+    // Where the view ends up, saved instead of the view while it's moving
+    pub target: CameraView,
+    // Offset of the whole screen
+    pub shake: TweenBox<glam::Vec2>,
+    // Drawn over the whole screen
+    pub flash: TweenBox<graphics::Color>,
+}
+
+impl Camera {
+    pub fn new(view: CameraView) -> Self {
+        Camera {
+            view: Box::new(NonTweener::new(view)),
+            target: view,
+            shake: Box::new(NonTweener::new(glam::Vec2::zero())),
+            flash: Box::new(NonTweener::new(graphics::Color::new(0.0, 0.0, 0.0, 0.0))),
+        }
+    }
+
+    pub fn move_to(&mut self, target: CameraView, duration: f32) {
+        let from = *self.view.get_current();
+        self.target = target;
+        self.view = Box::new(TargetTweener::new(
+            duration,
+            from,
+            move |view: &mut CameraView, progress| {
+                *view = from.lerp(&target, smoothstep(progress));
+            },
+        ));
+    }
+
+    /// Shakes the screen by up to `strength` pixels.
+    pub fn shake(&mut self, strength: f32, duration: f32) {
+        self.shake = Box::new(TargetTweener::new(
+            duration,
+            glam::Vec2::zero(),
+            move |offset: &mut glam::Vec2, progress| {
+                let angle = progress * std::f32::consts::PI * 2.0;
+                *offset = glam::Vec2::new((angle * 9.0).sin(), (angle * 7.0).cos())
+                    * strength
+                    * (1.0 - progress);
+            },
+        ));
+    }
+
+    /// Covers the screen in `color` and fades it out.
+    pub fn flash(&mut self, color: graphics::Color, duration: f32) {
+        self.flash = Box::new(TargetTweener::new(
+            duration,
+            color,
+            move |current: &mut graphics::Color, progress| {
+                current.a = color.a * (1.0 - progress);
+            },
+        ));
+    }
+
+    pub fn finish(&mut self) {
+        self.view.finish();
+        self.shake.finish();
+        self.flash.finish();
+    }
+
+    /// The transformation the screen is drawn with while shaking.
+    pub fn transform(&self) -> glam::Mat4 {
+        let offset = self.shake.get_current();
+        glam::Mat4::from_translation(glam::Vec3::new(offset.x, offset.y, 0.0))
+    }
+
+    pub fn draw_flash(&self, ctx: &mut Context, param: DrawParam) -> ggez::GameResult {
+        let color = *self.flash.get_current();
+        if color.a <= 0.0 {
+            return Ok(());
+        }
+        let size = crate::helpers::target_size();
+        Mesh::new_rectangle(
+            ctx,
+            DrawMode::Fill(FillOptions::DEFAULT),
+            Rect::new(0.0, 0.0, size.x, size.y),
+            color,
+        )?
+        .draw(ctx, param)
+    }
+}
+
+impl Update for Camera {
+    fn update(&mut self, dt: f32) {
+        self.view.update(dt);
+        self.shake.update(dt);
+        self.flash.update(dt);
+    }
+}
+
+#[test]
+fn test_camera_view() {
+    // Same aspect ratio as the screen
+    let (dest, scale) = CameraView::default().place(glam::Vec2::new(640.0, 360.0));
+    assert_eq!((dest, scale), (glam::Vec2::zero(), 2.0));
+
+    // Twice as wide as the screen, looking at the right edge
+    let view = CameraView {
+        x: 1.0,
+        ..CameraView::default()
+    };
+    let (dest, scale) = view.place(glam::Vec2::new(2560.0, 720.0));
+    assert_eq!((dest, scale), (glam::Vec2::new(-1280.0, 0.0), 1.0));
+
+    let view = CameraView {
+        zoom: 2.0,
+        ..CameraView::default()
+    };
+    let (dest, scale) = view.place(glam::Vec2::new(1280.0, 720.0));
+    assert_eq!((dest, scale), (glam::Vec2::new(-640.0, -360.0), 2.0));
+    assert_eq!(
+        view.parallax(0.5),
+        CameraView {
+            zoom: 1.5,
+            ..CameraView::default()
+        }
+    );
+}
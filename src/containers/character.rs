@@ -1,53 +1,177 @@
 use ggez::{graphics, Context};
-use ggez::{
-    graphics::{DrawParam, Drawable},
-    mint,
-};
+use ggez::{graphics::DrawParam, mint};
 
-use crate::{helpers::Position, states::game::Character, tween::TweenBox};
+use crate::{
+    helpers::Position,
+    placement::{Placement, XPosition},
+    states::game::{Character, CharacterLayer},
+    tween::TweenBox,
+};
 
 use derive_new::new;
 
+use super::{filter::StageFilter, Update};
+
 #[derive(new)]
 pub struct CharacterContainer {
     #[new(default)]
     pub current: Vec<TweenBox<Character>>,
+    // Removed characters that are still playing their exit transition
+    #[new(default)]
+    pub leaving: Vec<TweenBox<Character>>,
+    // Sprite folder of the speaker, the characters that aren't speaking are dimmed if set
+    #[new(default)]
+    pub speaker: Option<String>,
 }
 
-impl Drawable for CharacterContainer {
-    fn draw(&self, ctx: &mut Context, param: DrawParam) -> ggez::GameResult {
-        for (n, character) in self.current.iter().enumerate() {
-            let character = character.get_current();
+const DIM_DURATION: f32 = 0.25;
 
-            let height = crate::helpers::target_size().y * (4.0 / 5.0);
-            let target_size: mint::Point2<f32> = mint::Point2 {
-                x: height * (character.image.width() as f32 / character.image.height() as f32),
-                y: height,
-            };
+impl CharacterContainer {
+    /// The x coordinate the `n`th character is centered on with `placement`,
+    /// `n` can be the number of characters for one that's about to enter.
+    pub fn center_x(&self, n: usize, placement: &Placement) -> f32 {
+        let is_auto = |character: &&TweenBox<Character>| {
+            character.get_current().position.x == XPosition::Auto
+        };
+        let before = self.current[..n].iter().filter(is_auto).count();
+        let after = self.current.iter().skip(n + 1).filter(is_auto).count();
+        let count = before + after + (placement.x == XPosition::Auto) as usize;
+        placement.center_x(crate::helpers::target_size().x, (before, count))
+    }
 
-            let x_position = (crate::helpers::target_size().x as f32
-                / (self.current.len() as f32 + 1.0))
-                * (n as f32 + 1.0)
-                - (target_size.x / 2.0);
-
-            graphics::draw(
-                ctx,
-                &character.image,
-                graphics::DrawParam::new()
-                    .dest(
-                        Position::BottomLeft
-                            .add_in(ctx, glam::Vec2::new(x_position, target_size.y)),
-                    )
-                    .scale(mint::Vector2 {
-                        x: target_size.x / character.image.width() as f32,
-                        y: target_size.y / character.image.height() as f32,
-                    })
-                    .color(graphics::Color {
-                        a: character.alpha * param.color.a,
-                        ..graphics::WHITE
-                    }),
-            )?;
+    /// Completes all transitions instantly.
+    pub fn finish(&mut self) {
+        for character in &mut self.current {
+            character.finish();
+            let target = dim_target(&self.speaker, &character.get_current().sprite);
+            character.get_current_mut().dim = target;
+        }
+        self.leaving.clear();
+    }
+}
+
+fn dim_target(speaker: &Option<String>, sprite: &str) -> f32 {
+    match speaker {
+        Some(speaker) if speaker != sprite => 1.0,
+        _ => 0.0,
+    }
+}
+
+fn draw_character(
+    ctx: &mut Context,
+    character: &Character,
+    center_x: f32,
+    param: DrawParam,
+    filter: &StageFilter,
+) -> ggez::GameResult {
+    let brightness = 1.0 - 0.45 * character.dim;
+    let color = |alpha| graphics::Color::new(brightness, brightness, brightness, alpha);
+    if let Some(previous_layers) = &character.previous_layers {
+        draw_layers(
+            ctx,
+            character,
+            center_x,
+            previous_layers,
+            color(character.alpha * (1.0 - character.swap) * param.color.a),
+            filter,
+        )?;
+    }
+    draw_layers(
+        ctx,
+        character,
+        center_x,
+        &character.layers,
+        color(character.alpha * character.swap * param.color.a),
+        filter,
+    )
+}
+
+/// Composites the layers, sized and positioned by the first one.
+fn draw_layers(
+    ctx: &mut Context,
+    character: &Character,
+    center_x: f32,
+    layers: &[CharacterLayer],
+    color: graphics::Color,
+    filter: &StageFilter,
+) -> ggez::GameResult {
+    let base = match layers.first() {
+        Some(base) => &base.image,
+        None => return Ok(()),
+    };
+    let placement = &character.position;
+    // Dimmed characters are a bit smaller so the speaker stands out
+    let size = character.image_size(base) * (1.0 - 0.03 * character.dim);
+    let x_position = center_x - (size.x / 2.0) + character.offset.x;
+
+    let dest = Position::BottomLeft.add_in(
+        ctx,
+        glam::Vec2::new(x_position, size.y - placement.y_offset - character.offset.y),
+    );
+    let scale = size.x / base.width() as f32;
+
+    for layer in layers {
+        filter.draw_image(
+            ctx,
+            &layer.path,
+            &layer.image,
+            graphics::DrawParam::new()
+                .dest(dest + layer.offset * scale)
+                .scale(mint::Vector2 { x: scale, y: scale })
+                .color(color),
+        )?;
+    }
+    Ok(())
+}
+
+impl CharacterContainer {
+    pub fn draw(
+        &self,
+        ctx: &mut Context,
+        param: DrawParam,
+        filter: &StageFilter,
+    ) -> ggez::GameResult {
+        for character in &self.leaving {
+            let character = character.get_current();
+            // Leaving characters are placed in pixels
+            let center_x = character
+                .position
+                .center_x(crate::helpers::target_size().x, (0, 1));
+            draw_character(ctx, character, center_x, param, filter)?;
+        }
+        // Speakers are drawn in front of everyone else
+        let (dimmed, speaking): (Vec<_>, Vec<_>) = self
+            .current
+            .iter()
+            .enumerate()
+            .map(|(n, character)| {
+                let character = character.get_current();
+                (character, self.center_x(n, &character.position))
+            })
+            .partition(|(character, _)| character.dim >= 0.5);
+        for (character, center_x) in dimmed.into_iter().chain(speaking) {
+            draw_character(ctx, character, center_x, param, filter)?;
         }
         Ok(())
     }
 }
+
+impl Update for CharacterContainer {
+    fn update(&mut self, dt: f32) {
+        for character in &mut self.current {
+            character.update(dt);
+            let target = dim_target(&self.speaker, &character.get_current().sprite);
+            let current = character.get_current_mut();
+            let step = dt / DIM_DURATION;
+            current.dim = if current.dim < target {
+                (current.dim + step).min(target)
+            } else {
+                (current.dim - step).max(target)
+            };
+        }
+        for character in &mut self.leaving {
+            character.update(dt);
+        }
+        self.leaving.retain(|character| !character.is_done());
+    }
+}
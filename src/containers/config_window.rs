@@ -15,7 +15,9 @@ impl Drawable for VolumeControl {
 pub struct ConfigWindow {
     pub panel: Mesh,
     pub exit_button: Button,
-    pub volume_controls: StackContainer<VolumeControl, &'static str>,
+    pub volume_controls: StackContainer<VolumeControl, String>,
+    pub text_controls: StackContainer<VolumeControl, &'static str>,
+    pub skip_mode_button: Button,
 }
 
 impl Drawable for ConfigWindow {
@@ -23,6 +25,8 @@ impl Drawable for ConfigWindow {
         self.panel.draw(ctx, param)?;
         self.exit_button.draw(ctx, param)?;
         self.volume_controls.draw(ctx, param)?;
+        self.text_controls.draw(ctx, param)?;
+        self.skip_mode_button.draw(ctx, param)?;
         Ok(())
     }
 }
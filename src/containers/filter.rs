@@ -0,0 +1,334 @@
+use ggez::{
+    graphics::{self, DrawMode, DrawParam, Drawable, FillOptions, Mesh, Rect},
+    Context,
+};
+
+use crate::{
+    resource_manager::ResourceManager,
+    tween::{NonTweener, TargetTweener, TweenBox},
+};
+
+use super::Update;
+
+/// A color grade applied to every pixel of the backgrounds and characters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum Grade {
+    Night,
+    Sepia,
+    Grayscale,
+}
+
+impl Grade {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "night" => Some(Grade::Night),
+            "sepia" => Some(Grade::Sepia),
+            "grayscale" | "greyscale" => Some(Grade::Grayscale),
+            _ => None,
+        }
+    }
+
+    // Each row gives the new red, green and blue from the old ones
+    fn matrix(self) -> [[f32; 3]; 3] {
+        match self {
+            // Darker and bluer
+            Grade::Night => [[0.3, 0.1, 0.05], [0.1, 0.35, 0.1], [0.1, 0.2, 0.65]],
+            Grade::Sepia => [
+                [0.393, 0.769, 0.189],
+                [0.349, 0.686, 0.168],
+                [0.272, 0.534, 0.131],
+            ],
+            Grade::Grayscale => [[0.299, 0.587, 0.114]; 3],
+        }
+    }
+
+    /// Grades RGBA pixels in place.
+    pub fn apply(self, pixels: &mut [u8]) {
+        let matrix = self.matrix();
+        for pixel in pixels.chunks_exact_mut(4) {
+            let rgb = [pixel[0] as f32, pixel[1] as f32, pixel[2] as f32];
+            for (channel, row) in pixel.iter_mut().zip(&matrix) {
+                let value = row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2];
+                *channel = value.min(255.0) as u8;
+            }
+        }
+    }
+}
+
+/// What the filters are set to, saved instead of the tweens.
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+pub struct FilterState {
+    pub grade: Option<Grade>,
+    // How dark the edges of the screen are, 0 without a vignette
+    pub vignette: f32,
+    pub vignette_over_ui: bool,
+    // The color the screen is faded to, transparent when it isn't
+    pub fade: [f32; 4],
+    pub fade_over_ui: bool,
+}
+
+pub struct StageFilter {
+    // Graded images drawn over the stage bottom to top, with how opaque they are
+    pub grades: TweenBox<Vec<(Grade, f32)>>,
+    pub vignette: TweenBox<f32>,
+    pub fade: TweenBox<graphics::Color>,
+    // Where the tweens end up
+    pub target: FilterState,
+    resources: &'static ResourceManager,
+    vignette_image: graphics::Image,
+}
+
+impl StageFilter {
+    pub fn new(
+        ctx: &mut Context,
+        resources: &'static ResourceManager,
+        state: FilterState,
+    ) -> ggez::GameResult<Self> {
+        Ok(StageFilter {
+            grades: Box::new(NonTweener::new(
+                state.grade.into_iter().map(|grade| (grade, 1.0)).collect(),
+            )),
+            vignette: Box::new(NonTweener::new(state.vignette)),
+            fade: Box::new(NonTweener::new(state.fade.into())),
+            target: state,
+            resources,
+            vignette_image: vignette_image(ctx)?,
+        })
+    }
+
+    /// Sets every filter without tweening.
+    pub fn reset(&mut self, state: FilterState) {
+        self.resources
+            .retain_graded(&state.grade.into_iter().collect::<Vec<_>>());
+        self.grades = Box::new(NonTweener::new(
+            state.grade.into_iter().map(|grade| (grade, 1.0)).collect(),
+        ));
+        self.vignette = Box::new(NonTweener::new(state.vignette));
+        self.fade = Box::new(NonTweener::new(state.fade.into()));
+        self.target = state;
+    }
+
+    /// Fades the new grade in over the current one, or the current one out if there's none.
+    pub fn set_grade(&mut self, grade: Option<Grade>, duration: f32) {
+        // The grade on top is the one that's mostly visible
+        let from = self.grades.get_current().last().copied();
+        self.target.grade = grade;
+        let kept = from
+            .map(|(from, _)| from)
+            .into_iter()
+            .chain(grade)
+            .collect::<Vec<_>>();
+        self.resources.retain_graded(&kept);
+        self.grades = Box::new(TargetTweener::new(
+            duration,
+            from.into_iter().collect(),
+            move |grades: &mut Vec<(Grade, f32)>, progress| {
+                grades.clear();
+                match (from, grade) {
+                    (_, Some(grade)) if progress >= 1.0 => grades.push((grade, 1.0)),
+                    (from, Some(grade)) => {
+                        grades.extend(from);
+                        grades.push((grade, progress));
+                    }
+                    (Some((from, strength)), None) => {
+                        grades.push((from, strength * (1.0 - progress)))
+                    }
+                    (None, None) => {}
+                }
+            },
+        ));
+    }
+
+    pub fn set_vignette(&mut self, strength: f32, over_ui: bool, duration: f32) {
+        let from = *self.vignette.get_current();
+        self.target.vignette = strength;
+        // Fading out happens where the vignette already is
+        if strength > 0.0 {
+            self.target.vignette_over_ui = over_ui;
+        }
+        self.vignette = Box::new(TargetTweener::new(
+            duration,
+            from,
+            move |current: &mut f32, progress| {
+                *current = from + (strength - from) * progress;
+            },
+        ));
+    }
+
+    /// Fades the screen to `color`, or back from the current color if it's None.
+    pub fn set_fade(&mut self, color: Option<graphics::Color>, over_ui: bool, duration: f32) {
+        let from = *self.fade.get_current();
+        let to = color.unwrap_or(graphics::Color { a: 0.0, ..from });
+        self.target.fade = to.into();
+        if color.is_some() {
+            self.target.fade_over_ui = over_ui;
+        }
+        self.fade = Box::new(TargetTweener::new(
+            duration,
+            from,
+            move |current: &mut graphics::Color, progress| {
+                let lerp = |from: f32, to: f32| from + (to - from) * progress;
+                *current = graphics::Color::new(
+                    lerp(from.r, to.r),
+                    lerp(from.g, to.g),
+                    lerp(from.b, to.b),
+                    lerp(from.a, to.a),
+                );
+            },
+        ));
+    }
+
+    pub fn finish(&mut self) {
+        self.grades.finish();
+        self.vignette.finish();
+        self.fade.finish();
+    }
+
+    /// Draws `image`, loaded from `path`, with the grades over it.
+    pub fn draw_image(
+        &self,
+        ctx: &mut Context,
+        path: &str,
+        image: &graphics::Image,
+        param: DrawParam,
+    ) -> ggez::GameResult {
+        let grades = self.grades.get_current();
+        let strengths = grades
+            .iter()
+            .map(|(_, strength)| *strength)
+            .collect::<Vec<_>>();
+        let alphas = layer_alphas(&strengths, param.color.a);
+        for (n, alpha) in alphas.into_iter().enumerate() {
+            if alpha <= 0.0 {
+                continue;
+            }
+            let color = graphics::Color {
+                a: alpha,
+                ..param.color
+            };
+            match n.checked_sub(1) {
+                None => graphics::draw(ctx, image, param.color(color))?,
+                Some(n) => {
+                    let graded = self.resources.get_graded_image(ctx, path, grades[n].0)?;
+                    graphics::draw(ctx, &graded, param.color(color))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws the vignette and fade that go under the UI, or over it if `over_ui` is set.
+    pub fn draw_overlays(
+        &self,
+        ctx: &mut Context,
+        param: DrawParam,
+        over_ui: bool,
+    ) -> ggez::GameResult {
+        let size = crate::helpers::target_size();
+        let vignette = *self.vignette.get_current();
+        if vignette > 0.0 && self.target.vignette_over_ui == over_ui {
+            self.vignette_image.draw(
+                ctx,
+                DrawParam::new()
+                    .scale([
+                        size.x / self.vignette_image.width() as f32,
+                        size.y / self.vignette_image.height() as f32,
+                    ])
+                    .color(graphics::Color::new(
+                        1.0,
+                        1.0,
+                        1.0,
+                        vignette * param.color.a,
+                    )),
+            )?;
+        }
+        let fade = *self.fade.get_current();
+        if fade.a > 0.0 && self.target.fade_over_ui == over_ui {
+            Mesh::new_rectangle(
+                ctx,
+                DrawMode::Fill(FillOptions::DEFAULT),
+                Rect::new(0.0, 0.0, size.x, size.y),
+                fade,
+            )?
+            .draw(ctx, param)?;
+        }
+        Ok(())
+    }
+}
+
+/// The alpha to draw the image and then each grade over it with, so the grades make up
+/// `strengths` of what's visible and all of them together are `alpha` opaque.
+fn layer_alphas(strengths: &[f32], alpha: f32) -> Vec<f32> {
+    // How much of the result each layer is, every grade covers part of those under it
+    let mut shares = vec![alpha];
+    for strength in strengths {
+        for share in &mut shares {
+            *share *= 1.0 - strength;
+        }
+        shares.push(alpha * strength);
+    }
+    // Layers are drawn more opaque to make up for what's drawn over them
+    let mut uncovered = 1.0;
+    let mut alphas = vec![0.0; shares.len()];
+    for (n, share) in shares.iter().enumerate().rev() {
+        if uncovered > 0.0 {
+            alphas[n] = (share / uncovered).min(1.0);
+        }
+        uncovered *= 1.0 - alphas[n];
+    }
+    alphas
+}
+
+/// Black towards the corners of the screen and transparent in the middle.
+fn vignette_image(ctx: &mut Context) -> ggez::GameResult<graphics::Image> {
+    const WIDTH: u16 = 160;
+    const HEIGHT: u16 = 90;
+    let center = glam::Vec2::new(WIDTH as f32 / 2.0, HEIGHT as f32 / 2.0);
+    let pixels = (0..WIDTH as u32 * HEIGHT as u32)
+        .flat_map(|n| {
+            let point = glam::Vec2::new(
+                (n % WIDTH as u32) as f32 + 0.5,
+                (n / WIDTH as u32) as f32 + 0.5,
+            );
+            // 0 in the middle and 1 in the corners
+            let distance = ((point - center) / center).length() / std::f32::consts::SQRT_2;
+            let alpha = ((distance - 0.35) / 0.65).max(0.0).min(1.0).powf(1.5);
+            vec![0, 0, 0, (alpha * 255.0) as u8]
+        })
+        .collect::<Vec<_>>();
+    graphics::Image::from_rgba8(ctx, WIDTH, HEIGHT, &pixels)
+}
+
+impl Update for StageFilter {
+    fn update(&mut self, dt: f32) {
+        self.grades.update(dt);
+        self.vignette.update(dt);
+        self.fade.update(dt);
+    }
+}
+
+#[test]
+fn test_grade() {
+    let mut pixels = vec![255, 0, 0, 128, 10, 200, 30, 255];
+    Grade::Grayscale.apply(&mut pixels);
+    assert_eq!(pixels, vec![76, 76, 76, 128, 123, 123, 123, 255]);
+
+    // Channels that would go over the maximum are clamped
+    let mut pixels = vec![255, 255, 255, 255];
+    Grade::Sepia.apply(&mut pixels);
+    assert_eq!(pixels, vec![255, 255, 238, 255]);
+
+    assert_eq!(Grade::parse("night"), Some(Grade::Night));
+    assert_eq!(Grade::parse("blue"), None);
+}
+
+#[test]
+fn test_layer_alphas() {
+    assert_eq!(layer_alphas(&[], 0.5), vec![0.5]);
+    assert_eq!(layer_alphas(&[1.0], 1.0), vec![0.0, 1.0]);
+    assert_eq!(layer_alphas(&[0.5], 1.0), vec![1.0, 0.5]);
+    // Half transparent characters stay half transparent while a grade fades in
+    let alphas = layer_alphas(&[0.5], 0.5);
+    assert_eq!(alphas[1], 0.25);
+    assert!((1.0 - (1.0 - alphas[0]) * (1.0 - alphas[1]) - 0.5).abs() < 1e-6);
+}
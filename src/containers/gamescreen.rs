@@ -1,36 +1,101 @@
 use ggez::{
-    graphics::{DrawParam, Drawable},
+    graphics::{self, DrawParam, Drawable},
     Context,
 };
 
+use crate::helpers::Position;
+
 use super::{
-    background::BackgroundContainer, button::Button, character::CharacterContainer,
+    background::BackgroundContainer, backlog_window::BacklogWindow, button::Button, camera::Camera,
+    character::CharacterContainer, filter::StageFilter, glossary_window::GlossaryWindow,
+    nvl::NvlPage, particles::ParticleLayer, save_window::SaveWindow, sprite::Sprite,
     stackcontainer::StackContainer, textbox::TextBox, ui::UI, Update,
 };
 
+// Seconds a notice is shown, the last half second fades it out
+const NOTICE_DURATION: f32 = 3.0;
+const NOTICE_FADE: f32 = 0.5;
+
 pub enum Action {
     Choice(StackContainer<Button, u32>),
     Text(Box<TextBox>),
     None,
 }
 
+#[allow(clippy::large_enum_variant)]
+pub enum Window {
+    None,
+    Save(SaveWindow),
+    Backlog(BacklogWindow),
+    Glossary(GlossaryWindow),
+}
+
+impl Window {
+    pub fn is_open(&self) -> bool {
+        !matches!(self, Window::None)
+    }
+}
+
+/// A short message shown over the game, e.g. when a save couldn't be loaded.
+pub struct Notice {
+    text: Sprite<graphics::Text>,
+    // Seconds until it's hidden
+    remaining: f32,
+}
+
+impl Notice {
+    pub fn new(ctx: &Context, message: &str) -> Self {
+        Self {
+            text: Sprite {
+                content: graphics::Text::new(message),
+                param: DrawParam::new()
+                    .dest(Position::TopLeft.add_in(ctx, glam::Vec2::new(20.0, 20.0))),
+            },
+            remaining: NOTICE_DURATION,
+        }
+    }
+}
+
 pub struct GameScreen {
     pub current_background: Option<BackgroundContainer>,
     pub current_characters: CharacterContainer,
+    pub camera: Camera,
+    pub weather: Option<ParticleLayer>,
+    pub filter: StageFilter,
     pub action: Action,
+    // Some while in NVL mode
+    pub nvl: Option<NvlPage>,
     pub ui: UI,
+    pub window: Window,
+    pub notice: Option<Notice>,
     pub is_screenshot: bool,
 }
 
 impl Drawable for GameScreen {
     fn draw(&self, ctx: &mut Context, param: DrawParam) -> ggez::GameResult {
+        // Everything but the windows shakes
+        graphics::push_transform(ctx, Some(self.camera.transform().into()));
+        graphics::apply_transformations(ctx)?;
+
         if let Some(background) = &self.current_background {
-            background.draw(ctx, param)?;
+            background.draw(ctx, param, self.camera.view.get_current(), &self.filter)?;
+        }
+
+        let weather = self.weather.as_ref();
+        if let Some(weather) = weather.filter(|weather| !weather.front) {
+            weather.draw(ctx, param)?;
+        }
+        self.current_characters.draw(ctx, param, &self.filter)?;
+        if let Some(weather) = weather.filter(|weather| weather.front) {
+            weather.draw(ctx, param)?;
         }
 
-        self.current_characters.draw(ctx, param)?;
+        self.filter.draw_overlays(ctx, param, false)?;
 
         if !self.is_screenshot {
+            if let Some(nvl) = &self.nvl {
+                nvl.draw(ctx, param)?;
+            }
             if let Action::Choice(container) = &self.action {
                 for (choice, _) in &container.children {
                     choice.draw(ctx, param)?;
@@ -41,22 +106,74 @@ impl Drawable for GameScreen {
 
             self.ui.draw(ctx, param)?;
         }
+        self.filter.draw_overlays(ctx, param, true)?;
+        self.camera.draw_flash(ctx, param)?;
+
+        graphics::pop_transform(ctx);
+        graphics::apply_transformations(ctx)?;
+
+        if let Window::Save(window) = &self.window {
+            window.draw(ctx, param)?;
+        } else if let Window::Backlog(window) = &self.window {
+            window.draw(ctx, param)?;
+        } else if let Window::Glossary(window) = &self.window {
+            window.draw(ctx, param)?;
+        }
+
+        if let Some(notice) = self.notice.as_ref().filter(|_| !self.is_screenshot) {
+            let mut param = param;
+            param.color.a *= (notice.remaining / NOTICE_FADE).min(1.0);
+            notice.text.draw(ctx, param)?;
+        }
 
         Ok(())
     }
 }
 
+impl GameScreen {
+    /// Skips to the end of the character, background and camera transitions.
+    pub fn finish_transitions(&mut self) {
+        self.current_characters.finish();
+        self.camera.finish();
+        self.filter.finish();
+        if let Some(background) = &mut self.current_background {
+            background.current.finish();
+        }
+    }
+
+    /// Keeps the line being shown visible on the NVL page once it's replaced.
+    pub fn keep_nvl_line(&mut self) {
+        if let Some(nvl) = &mut self.nvl {
+            if let Action::Text(mut text) = std::mem::replace(&mut self.action, Action::None) {
+                text.content.content.finish();
+                nvl.previous.push(text);
+                nvl.lines.extend(nvl.current.take());
+            }
+        }
+    }
+}
+
 impl Update for GameScreen {
     fn update(&mut self, dt: f32) {
-        for character in &mut self.current_characters.current {
-            character.update(dt);
+        self.current_characters.update(dt);
+        self.camera.update(dt);
+        self.filter.update(dt);
+        if let Some(weather) = &mut self.weather {
+            weather.update(dt);
         }
         if let Some(current_background) = &mut self.current_background {
-            current_background.current.update(dt);
+            current_background.update(dt);
         }
 
         if let Action::Text(text) = &mut self.action {
             text.update(dt);
         }
+
+        if let Some(notice) = &mut self.notice {
+            notice.remaining -= dt;
+            if notice.remaining <= 0.0 {
+                self.notice = None;
+            }
+        }
     }
 }
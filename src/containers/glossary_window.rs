@@ -0,0 +1,19 @@
+use ggez::graphics::{DrawParam, Drawable, Mesh, Text};
+
+use super::sprite::Sprite;
+
+/// Popup with the definition of a glossary term, closed by clicking anywhere.
+pub struct GlossaryWindow {
+    pub panel: Mesh,
+    pub name: Sprite<Text>,
+    pub definition: Sprite<Text>,
+}
+
+impl Drawable for GlossaryWindow {
+    fn draw(&self, ctx: &mut ggez::Context, param: DrawParam) -> ggez::GameResult {
+        self.panel.draw(ctx, param)?;
+        self.name.draw(ctx, param)?;
+        self.definition.draw(ctx, param)?;
+        Ok(())
+    }
+}
@@ -1,11 +1,18 @@
 pub mod background;
+pub mod backlog_window;
 pub mod button;
+pub mod camera;
 pub mod character;
 pub mod config_window;
 pub mod credits_window;
+pub mod filter;
 pub mod gamescreen;
+pub mod glossary_window;
 pub mod mainmenuscreen;
+pub mod nvl;
+pub mod particles;
 pub mod rich_text;
+pub mod save_window;
 pub mod slider;
 pub mod sprite;
 pub mod stackcontainer;
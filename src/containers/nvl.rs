@@ -0,0 +1,64 @@
+use ggez::{
+    graphics::{self, DrawMode, DrawParam, Drawable, FillOptions, Mesh, Rect},
+    Context,
+};
+
+use crate::save::SavedLine;
+
+use super::textbox::TextBox;
+
+/// Full-screen panel the lines of NVL mode accumulate on until the page is cleared.
+pub struct NvlPage {
+    pub panel: Mesh,
+    // Area the lines are laid out in
+    pub bounds: Rect,
+    // Lines shown before the current one, fully revealed
+    pub previous: Vec<Box<TextBox>>,
+    pub lines: Vec<SavedLine>,
+    // The line in the textbox of the action, moved to `previous` when the next line is shown
+    pub current: Option<SavedLine>,
+    // Where the next line starts
+    pub next_y: f32,
+}
+
+impl NvlPage {
+    pub fn new(ctx: &mut Context) -> ggez::GameResult<Self> {
+        let size = crate::helpers::target_size();
+        let bounds = Rect::new(120.0, 60.0, size.x - 240.0, size.y - 140.0);
+        Ok(NvlPage {
+            panel: Mesh::new_rectangle(
+                ctx,
+                DrawMode::Fill(FillOptions::DEFAULT),
+                Rect::new(0.0, 0.0, size.x, size.y),
+                graphics::Color {
+                    r: 0.0,
+                    g: 0.0,
+                    b: 0.0,
+                    a: 0.6,
+                },
+            )?,
+            bounds,
+            previous: Vec::new(),
+            lines: Vec::new(),
+            current: None,
+            next_y: bounds.y,
+        })
+    }
+
+    pub fn clear(&mut self) {
+        self.previous.clear();
+        self.lines.clear();
+        self.current = None;
+        self.next_y = self.bounds.y;
+    }
+}
+
+impl Drawable for NvlPage {
+    fn draw(&self, ctx: &mut Context, param: DrawParam) -> ggez::GameResult {
+        self.panel.draw(ctx, param)?;
+        for line in &self.previous {
+            line.draw(ctx, param)?;
+        }
+        Ok(())
+    }
+}
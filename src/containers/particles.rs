@@ -0,0 +1,248 @@
+use ggez::{
+    graphics::{self, spritebatch::SpriteBatch, DrawParam, Drawable},
+    Context,
+};
+
+use crate::{config::ParticlePreset, resource_manager::ResourceManager, save::SavedWeather};
+
+use super::Update;
+
+/// xorshift64*, the same seed always gives the same numbers.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // A state of 0 would only ever give 0
+        Rng(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Between 0 and 1.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Particle {
+    pub position: glam::Vec2,
+    pub velocity: glam::Vec2,
+    pub size: f32,
+    // Offsets the sway so the particles don't move together
+    pub phase: f32,
+}
+
+// Particles leave the screen by this many pixels before coming back on the other side
+const MARGIN: f32 = 20.0;
+
+/// The simulation of a particle effect, apart from drawing.
+pub struct Particles {
+    pub preset: ParticlePreset,
+    pub rng: Rng,
+    pub particles: Vec<Particle>,
+    pub time: f32,
+}
+
+impl Particles {
+    /// Starts with the particles spread over the screen.
+    pub fn new(preset: ParticlePreset, seed: u64) -> Self {
+        let mut particles = Particles {
+            rng: Rng::new(seed),
+            particles: Vec::with_capacity(preset.count),
+            time: 0.0,
+            preset,
+        };
+        let screen = crate::helpers::target_size();
+        for _ in 0..particles.preset.count {
+            let position = glam::Vec2::new(
+                particles.rng.range(-MARGIN, screen.x + MARGIN),
+                particles.rng.range(-MARGIN, screen.y + MARGIN),
+            );
+            let particle = particles.spawn(position);
+            particles.particles.push(particle);
+        }
+        particles
+    }
+
+    fn spawn(&mut self, position: glam::Vec2) -> Particle {
+        let preset = &self.preset;
+        let spread = preset.spread;
+        let velocity = preset.velocity
+            + glam::Vec2::new(
+                self.rng.range(-spread.x, spread.x),
+                self.rng.range(-spread.y, spread.y),
+            );
+        Particle {
+            position,
+            velocity,
+            size: self.rng.range(preset.size.x, preset.size.y),
+            phase: self.rng.range(0.0, std::f32::consts::PI * 2.0),
+        }
+    }
+
+    /// Where `particle` is drawn, including its sway.
+    pub fn draw_position(&self, particle: &Particle) -> glam::Vec2 {
+        let sway = self.preset.sway * (self.time + particle.phase).sin();
+        particle.position + glam::Vec2::new(sway, 0.0)
+    }
+}
+
+impl Update for Particles {
+    fn update(&mut self, dt: f32) {
+        self.time += dt;
+        let screen = crate::helpers::target_size();
+        for n in 0..self.particles.len() {
+            let mut position = self.particles[n].position + self.particles[n].velocity * dt;
+            // Particles that left the screen come back on the opposite side
+            let (left, right) = (-MARGIN, screen.x + MARGIN);
+            let (top, bottom) = (-MARGIN, screen.y + MARGIN);
+            if position.y > bottom || position.y < top {
+                position.y = if position.y > bottom { top } else { bottom };
+                position.x = self.rng.range(left, right);
+            } else if position.x > right || position.x < left {
+                position.x = if position.x > right { left } else { right };
+                position.y = self.rng.range(top, bottom);
+            } else {
+                self.particles[n].position = position;
+                continue;
+            }
+            let particle = self.spawn(position);
+            self.particles[n] = particle;
+        }
+    }
+}
+
+/// Particles drawn over the background or, if `front` is set, over the characters.
+pub struct ParticleLayer {
+    pub name: String,
+    pub seed: u64,
+    pub front: bool,
+    pub particles: Particles,
+    image: graphics::Image,
+}
+
+impl ParticleLayer {
+    pub fn new(
+        ctx: &mut Context,
+        resources: &'static ResourceManager,
+        weather: SavedWeather,
+    ) -> ggez::GameResult<Self> {
+        let preset = resources
+            .get_config()
+            .particles
+            .get(&weather.name)
+            .cloned()
+            .ok_or_else(|| {
+                ggez::GameError::ResourceLoadError(format!(
+                    "Unknown particle preset {}",
+                    weather.name
+                ))
+            })?;
+        let image = match &preset.image {
+            Some(path) => resources.try_get_image(ctx, path)?,
+            None => soft_dot(ctx)?,
+        };
+        Ok(ParticleLayer {
+            name: weather.name,
+            seed: weather.seed,
+            front: weather.front,
+            particles: Particles::new(preset, weather.seed),
+            image,
+        })
+    }
+
+    pub fn saved(&self) -> SavedWeather {
+        SavedWeather {
+            name: self.name.clone(),
+            seed: self.seed,
+            front: self.front,
+        }
+    }
+}
+
+/// A white circle that fades out towards its edge.
+fn soft_dot(ctx: &mut Context) -> ggez::GameResult<graphics::Image> {
+    const SIZE: u16 = 16;
+    let center = SIZE as f32 / 2.0;
+    let pixels = (0..SIZE * SIZE)
+        .flat_map(|n| {
+            let (x, y) = ((n % SIZE) as f32 + 0.5, (n / SIZE) as f32 + 0.5);
+            let distance = ((x - center).powi(2) + (y - center).powi(2)).sqrt() / center;
+            let alpha = ((1.0 - distance) * 2.0).max(0.0).min(1.0);
+            vec![255, 255, 255, (alpha * 255.0) as u8]
+        })
+        .collect::<Vec<_>>();
+    graphics::Image::from_rgba8(ctx, SIZE, SIZE, &pixels)
+}
+
+impl Drawable for ParticleLayer {
+    fn draw(&self, ctx: &mut Context, param: DrawParam) -> ggez::GameResult {
+        let preset = &self.particles.preset;
+        let mut batch = SpriteBatch::new(self.image.clone());
+        let image_size = glam::Vec2::new(self.image.width() as f32, self.image.height() as f32);
+        for particle in &self.particles.particles {
+            let size = glam::Vec2::new(particle.size, particle.size * preset.stretch);
+            // Stretched particles point where they're going
+            let rotation = if preset.stretch != 1.0 {
+                -particle.velocity.x.atan2(particle.velocity.y)
+            } else {
+                0.0
+            };
+            batch.add(
+                DrawParam::new()
+                    .dest(self.particles.draw_position(particle))
+                    .offset([0.5, 0.5])
+                    .rotation(rotation)
+                    .scale(size / image_size)
+                    .color(graphics::Color {
+                        a: preset.color.a * param.color.a,
+                        ..preset.color
+                    }),
+            );
+        }
+        graphics::draw(ctx, &batch, DrawParam::new())
+    }
+}
+
+impl Update for ParticleLayer {
+    fn update(&mut self, dt: f32) {
+        self.particles.update(dt);
+    }
+}
+
+#[test]
+fn test_particles_deterministic() {
+    let preset = ParticlePreset::builtin("snow").unwrap();
+    let mut a = Particles::new(preset.clone(), 42);
+    let mut b = Particles::new(preset.clone(), 42);
+    for _ in 0..600 {
+        a.update(1.0 / 60.0);
+        b.update(1.0 / 60.0);
+    }
+    assert_eq!(a.particles, b.particles);
+    assert_eq!(a.particles.len(), preset.count);
+
+    let c = Particles::new(preset, 7);
+    assert_ne!(
+        Particles::new(ParticlePreset::builtin("snow").unwrap(), 42).particles,
+        c.particles
+    );
+
+    // Everything is still on screen after falling for a while
+    let screen = crate::helpers::target_size();
+    assert!(a.particles.iter().all(|p| p.position.y >= -MARGIN
+        && p.position.y <= screen.y + MARGIN
+        && p.position.x >= -MARGIN
+        && p.position.x <= screen.x + MARGIN));
+}
@@ -1,14 +1,35 @@
 use ggez::{
-    graphics::{Color, Drawable, Text, TextFragment},
-    Context,
+    graphics::{Color, Drawable, Rect, Text, TextFragment},
+    mint, Context,
 };
 
-#[derive(Debug)]
+use crate::markup::Markup;
+
+pub const LINK_COLOR: Color = Color {
+    r: 0.8,
+    g: 0.4,
+    b: 0.0,
+    a: 1.0,
+};
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Format {
-    Link(String), // the url
+    Link(String),     // the url
+    Glossary(String), // the glossary.ini section
+}
+
+impl Format {
+    /// `glossary:key` links to a glossary entry, anything else is a url.
+    pub fn from_target(target: String) -> Self {
+        match target.strip_prefix("glossary:") {
+            Some(key) => Format::Glossary(key.to_owned()),
+            None => Format::Link(target),
+        }
+    }
 }
 
-#[derive(Debug)]
+// start and end are glyph indices, newlines are not counted
+#[derive(Debug, Clone, PartialEq)]
 pub struct FormatEntry {
     pub format: Format,
     pub start: usize,
@@ -21,54 +42,44 @@ pub struct RichText {
 }
 
 impl RichText {
+    /// Adds `content` to `text`, with links written like in [`Markup`].
     pub fn new(content: &str, mut text: Text) -> Self {
-        let mut formatting = Vec::new();
-
-        let mut chars_it = content.chars().enumerate();
-
-        // count newlines, link length and parse character to offset the character index
-        let mut skip_char_count = 0;
-
-        while let Some((n, c)) = chars_it.by_ref().next() {
-            if c == '\n' {
-                skip_char_count += 1;
-            }
-            if c == '[' {
-                let link_text = chars_it
-                    .by_ref()
-                    .map(|(_, c)| c)
-                    .take_while(|c| *c != ']')
-                    .collect::<String>();
-                if let Some((_, c)) = chars_it.by_ref().next() {
-                    // false positive, it was not a link
-                    if c != '(' {
-                        text.add(c);
-                        continue;
-                    }
-                }
-                let link_url = chars_it
-                    .by_ref()
-                    .map(|(_, c)| c)
-                    .take_while(|c| *c != ')')
-                    .collect::<String>();
-                let link_url_len = link_url.len();
-                formatting.push(FormatEntry {
-                    start: n - skip_char_count - 1,                 // skip first (
-                    end: n + link_text.len() - skip_char_count - 1, // skip first (
-                    format: Format::Link(link_url),
-                });
-                for c in link_text.chars() {
-                    let mut frag: TextFragment = c.into();
-                    frag.color = Some(Color::from_rgb_u32(0xCC_66_00));
-                    text.add(frag);
-                }
-                skip_char_count += link_url_len + 4 // []() is 4 characters
-            } else {
-                text.add(c);
-            }
+        let markup = Markup::parse(content);
+        for c in &markup.chars {
+            let mut frag: TextFragment = c.c.into();
+            frag.color = c.color;
+            text.add(frag);
+        }
+        Self {
+            formatting: markup.formatting,
+            text,
         }
+    }
 
-        Self { formatting, text }
+    /// The formatting under `x`, `y` if the text is drawn at `dest`.
+    pub fn format_at(
+        &self,
+        ctx: &mut Context,
+        dest: mint::Point2<f32>,
+        x: f32,
+        y: f32,
+    ) -> Option<&Format> {
+        let positions = self.text.positions(ctx);
+        self.formatting
+            .iter()
+            .find(|format| {
+                let positions = &positions[format.start..format.end];
+                let bounds = Rect {
+                    x: positions[0].x + dest.x,
+                    y: positions[0].y + dest.y - ggez::graphics::DEFAULT_FONT_SCALE,
+                    w: (positions[positions.len() - 1].x + ggez::graphics::DEFAULT_FONT_SCALE)
+                        - positions[0].x,
+                    h: positions[positions.len() - 1].y
+                        - (positions[0].y - ggez::graphics::DEFAULT_FONT_SCALE),
+                };
+                bounds.contains(mint::Point2 { x, y })
+            })
+            .map(|format| &format.format)
     }
 
     /* Mouse up is impl'd in sprite.rs */
@@ -0,0 +1,49 @@
+use ggez::graphics::{self, DrawParam, Drawable, Mesh, Text};
+
+use crate::save::SlotId;
+
+use super::{button::Button, sprite::Sprite, stackcontainer::StackContainer};
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum SaveWindowMode {
+    Save,
+    Load,
+}
+
+pub struct SaveSlot {
+    pub button: Button,
+    pub thumbnail: Option<Sprite<graphics::Image>>,
+    pub info: Sprite<Text>,
+    pub is_loadable: bool,
+}
+
+impl Drawable for SaveSlot {
+    fn draw(&self, ctx: &mut ggez::Context, param: DrawParam) -> ggez::GameResult {
+        self.button.draw(ctx, param)?;
+        if let Some(thumbnail) = &self.thumbnail {
+            thumbnail.draw(ctx, param)?;
+        }
+        self.info.draw(ctx, param)?;
+        Ok(())
+    }
+}
+
+pub struct SaveWindow {
+    pub mode: SaveWindowMode,
+    pub panel: Mesh,
+    pub title: Sprite<Text>,
+    pub exit_button: Button,
+    pub slots: StackContainer<SaveSlot, SlotId>,
+    // Captured when the window was opened so the save screen itself isn't in the thumbnail
+    pub thumbnail: Option<image::RgbaImage>,
+}
+
+impl Drawable for SaveWindow {
+    fn draw(&self, ctx: &mut ggez::Context, param: DrawParam) -> ggez::GameResult {
+        self.panel.draw(ctx, param)?;
+        self.title.draw(ctx, param)?;
+        self.slots.draw(ctx, param)?;
+        self.exit_button.draw(ctx, param)?;
+        Ok(())
+    }
+}
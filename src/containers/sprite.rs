@@ -1,7 +1,7 @@
 use ggez::{
     event::MouseButton,
-    graphics::{DrawParam, Drawable, Rect},
-    mint, Context,
+    graphics::{DrawParam, Drawable},
+    Context,
 };
 
 use derive_new::new;
@@ -40,24 +40,12 @@ impl Sprite<RichText> {
         x: f32,
         y: f32,
     ) {
-        for format in &self.content.formatting {
-            let positions = &self.content.text.positions(ctx)[format.start..format.end];
-            let bounds = Rect {
-                x: positions[0].x + self.param.dest.x,
-                y: positions[0].y + self.param.dest.y - ggez::graphics::DEFAULT_FONT_SCALE,
-                w: (positions[positions.len() - 1].x + ggez::graphics::DEFAULT_FONT_SCALE)
-                    - positions[0].x,
-                h: positions[positions.len() - 1].y
-                    - (positions[0].y - ggez::graphics::DEFAULT_FONT_SCALE),
-            };
-            if bounds.contains(mint::Point2 { x, y }) {
-                match &format.format {
-                    Format::Link(url) => {
-                        webbrowser::open(url).unwrap();
-                    }
-                }
-                break;
+        match self.content.format_at(ctx, self.param.dest, x, y) {
+            Some(Format::Link(url)) => {
+                webbrowser::open(url).unwrap();
             }
+            // The glossary is only available in game
+            Some(Format::Glossary(..)) | None => {}
         }
     }
 }
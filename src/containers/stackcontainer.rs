@@ -7,6 +7,7 @@ use derive_new::new;
 pub enum Direction {
     Horizontal,
     Vertical,
+    Grid { columns: usize },
 }
 
 #[derive(new)]
@@ -32,6 +33,13 @@ impl<T, D> StackContainer<T, D> {
                 let y = self.position.y;
                 (x, y)
             }
+            Direction::Grid { columns } => {
+                let column = (n as usize % columns) as f32;
+                let row = (n as usize / columns) as f32;
+                let x = self.position.x + column * (self.cell_size.0 + self.spacing);
+                let y = self.position.y + row * (self.cell_size.1 + self.spacing);
+                (x, y)
+            }
         };
         Rect {
             x: pos.0,
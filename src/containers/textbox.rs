@@ -5,17 +5,55 @@ use ggez::{
 
 use crate::tween::TweenBox;
 
-use super::{sprite::Sprite, Update};
+use super::{
+    rich_text::{Format, RichText},
+    sprite::Sprite,
+    Update,
+};
 
 pub struct TextBox {
-    pub layer: Sprite<graphics::Image>,
+    // None on an NVL page
+    pub layer: Option<Sprite<graphics::Image>>,
+    pub portrait: Option<Sprite<graphics::Image>>,
     pub speaker: Option<Sprite<graphics::Text>>,
-    pub content: Sprite<TweenBox<graphics::Text>>,
+    pub content: Sprite<TweenBox<RichText>>,
+    // Continues to the next line by itself once the content is shown, set by `{nw}`
+    pub no_wait: bool,
+    // Played while the text is revealed
+    pub voice: Option<String>,
+    // How many times the voice has been played for this line
+    pub blipped: usize,
+}
+
+impl TextBox {
+    /// How many characters of the content are shown.
+    pub fn revealed(&self) -> usize {
+        self.content
+            .content
+            .get_current()
+            .text
+            .fragments()
+            .iter()
+            .take_while(|fragment| fragment.color.map_or(true, |color| color.a > 0.0))
+            .count()
+    }
+
+    pub fn format_at(&self, ctx: &mut Context, x: f32, y: f32) -> Option<&Format> {
+        self.content
+            .content
+            .get_current()
+            .format_at(ctx, self.content.param.dest, x, y)
+    }
 }
 
 impl Drawable for TextBox {
     fn draw(&self, ctx: &mut Context, parent_param: DrawParam) -> ggez::GameResult {
-        self.layer.draw(ctx, parent_param)?;
+        if let Some(layer) = &self.layer {
+            layer.draw(ctx, parent_param)?;
+        }
+        if let Some(portrait) = &self.portrait {
+            portrait.draw(ctx, parent_param)?;
+        }
         if let Some(speaker) = &self.speaker {
             speaker.draw(ctx, parent_param)?;
         }
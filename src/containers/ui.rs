@@ -8,6 +8,7 @@ pub enum MenuButtonId {
     Load,
     Skip,
     Auto,
+    Backlog,
 }
 
 pub struct UI {
@@ -1,30 +1,61 @@
 use crate::{
+    config::CharacterConfig,
     containers::{
+        character::CharacterContainer,
         gamescreen::{Action, GameScreen},
+        nvl::NvlPage,
+        rich_text::RichText,
         sprite::Sprite,
         textbox::TextBox,
     },
     helpers::{points_to_rect, Position},
+    markup::Markup,
     resource_manager::ResourceManager,
+    save::SavedLine,
     tween::Tweener,
 };
 use ggez::{
     graphics::{self, DrawParam},
     Context,
 };
+use log::warn;
+use std::sync::Once;
 
-pub fn load_text(
+// The tags are likely on many lines, a missing font is only warned about once
+static MISSING_BOLD_FONT: Once = Once::new();
+static MISSING_ITALIC_FONT: Once = Once::new();
+
+/// Where the parts of a line are drawn.
+struct Layout {
+    layer: Option<Sprite<graphics::Image>>,
+    portrait: Option<Sprite<graphics::Image>>,
+    speaker: glam::Vec2,
+    content: glam::Vec2,
+}
+
+/// The textbox at the bottom of the screen.
+fn adv_layout(
     ctx: &mut Context,
-    screen: &mut GameScreen,
+    characters: &CharacterContainer,
     resources: &'static ResourceManager,
     speaker: &Option<String>,
-    content: &str,
-) -> ggez::GameResult {
+    character: Option<&CharacterConfig>,
+    text: &mut graphics::Text,
+) -> Layout {
     let layer_bounds = points_to_rect(
         Position::BottomLeft.add_in(ctx, glam::Vec2::new(0.0, 240.0)),
         Position::BottomRight.add_in(ctx, glam::Vec2::new(0.0, 40.0)),
     );
-    let layer_image = resources.get_image(ctx, "/TextBox");
+    let config = resources.get_config();
+
+    // A bad textbox override falls back to the default textbox
+    let layer_image = match character.and_then(|c| c.textbox.as_deref()) {
+        Some(path) => resources.try_get_image(ctx, path).unwrap_or_else(|e| {
+            warn!("Unable to show textbox: {}", e);
+            resources.get_image(ctx, "/TextBox")
+        }),
+        None => resources.get_image(ctx, "/TextBox"),
+    };
     let layer_params = graphics::DrawParam::new()
         .dest([layer_bounds.x, layer_bounds.y])
         .scale([
@@ -32,46 +63,182 @@ pub fn load_text(
             layer_bounds.h / layer_image.height() as f32,
         ]);
 
-    let speaker_text = if let Some(speaker) = speaker {
-        let mut speaker_text = graphics::Text::new(speaker.as_str());
-        speaker_text.set_bounds([f32::INFINITY, f32::INFINITY], graphics::Align::Left);
-        let speaker_text_params = DrawParam::new()
-            .dest(Position::TopLeft.add_in_from(&layer_bounds, glam::Vec2::new(15.0, 20.0)))
-            .color(
-                resources
-                    .get_config()
-                    .characters
-                    .get(speaker)
-                    .copied()
-                    .unwrap_or_default()
-                    .color,
-            );
+    // Follows the expression of the speaker if they're on stage
+    let portrait_path = speaker.as_ref().zip(character).and_then(|(speaker, c)| {
+        let sprite = config.sprite_name(speaker);
+        match characters
+            .current
+            .iter()
+            .map(|on_stage| on_stage.get_current())
+            .find(|on_stage| on_stage.sprite == sprite)
+        {
+            Some(on_stage) => c.portrait_path(&on_stage.expression, &on_stage.layer_values),
+            None => c.portrait_path(
+                c.default_expression.as_deref().unwrap_or("Normal"),
+                &c.layer_values(None, "").unwrap_or_default(),
+            ),
+        }
+    });
+    let portrait = portrait_path.and_then(|path| {
+        // A missing portrait shouldn't stop the line from being shown
+        let image = resources
+            .try_get_image(ctx, &path)
+            .map_err(|e| warn!("Unable to show portrait: {}", e))
+            .ok()?;
+        let height = layer_bounds.h - 20.0;
+        let scale = height / image.height() as f32;
         Some(Sprite {
-            content: speaker_text,
-            param: speaker_text_params,
+            param: DrawParam::new()
+                .dest(Position::TopLeft.add_in_from(&layer_bounds, glam::Vec2::new(10.0, 10.0)))
+                .scale([scale, scale]),
+            content: image,
         })
-    } else {
-        None
-    };
+    });
+    // The text is moved to the right of the portrait
+    let text_x = 15.0
+        + portrait.as_ref().map_or(0.0, |portrait| {
+            portrait.content.width() as f32 * portrait.param.scale.x + 10.0
+        });
 
-    let mut text = graphics::Text::default();
-    for c in content.chars() {
-        text.add(graphics::TextFragment::new(c).color(graphics::Color {
-            a: 0.0,
-            ..graphics::WHITE
-        }));
-    }
     text.set_bounds(
-        [layer_bounds.w - 10.0, layer_bounds.h - 10.0],
+        [layer_bounds.w - text_x + 5.0, layer_bounds.h - 10.0],
         graphics::Align::Left,
     );
 
-    let text_tween = Tweener::new(text, |text, time, _| {
-        let cps = 75.0f32;
+    Layout {
+        layer: Some(Sprite {
+            content: layer_image,
+            param: layer_params,
+        }),
+        portrait,
+        speaker: Position::TopLeft.add_in_from(&layer_bounds, glam::Vec2::new(text_x, 20.0)),
+        content: Position::TopLeft.add_in_from(&layer_bounds, glam::Vec2::new(text_x, 55.0)),
+    }
+}
+
+const MIN_NVL_FONT_SCALE: f32 = 8.0;
 
+/// Below the previous line on the NVL page, starting a new page if it doesn't fit.
+fn nvl_layout(
+    ctx: &mut Context,
+    nvl: &mut NvlPage,
+    has_speaker: bool,
+    text: &mut graphics::Text,
+) -> Layout {
+    let bounds = nvl.bounds;
+    text.set_bounds([bounds.w, f32::INFINITY], graphics::Align::Left);
+    let speaker_height = if has_speaker { 35.0 } else { 0.0 };
+    let mut height = speaker_height + text.height(ctx) as f32;
+    // A line taller than a whole page is shrunk until it fits on one
+    let mut scale = graphics::DEFAULT_FONT_SCALE;
+    while height > bounds.h && scale > MIN_NVL_FONT_SCALE {
+        scale -= 1.0;
+        for fragment in text.fragments_mut() {
+            fragment.scale = Some(graphics::PxScale::from(scale));
+        }
+        height = speaker_height + text.height(ctx) as f32;
+    }
+    if !nvl.previous.is_empty() && nvl.next_y + height > bounds.bottom() {
+        nvl.clear();
+    }
+    let y = nvl.next_y;
+    nvl.next_y = y + height + 20.0;
+    Layout {
+        layer: None,
+        portrait: None,
+        speaker: glam::Vec2::new(bounds.x, y),
+        content: glam::Vec2::new(bounds.x, y + speaker_height),
+    }
+}
+
+pub fn load_text(
+    ctx: &mut Context,
+    screen: &mut GameScreen,
+    resources: &'static ResourceManager,
+    speaker: &Option<String>,
+    content: &str,
+) -> ggez::GameResult {
+    // The previous line stays on the page in NVL mode
+    screen.keep_nvl_line();
+    let config = resources.get_config();
+    let character = speaker
+        .as_ref()
+        .and_then(|speaker| config.characters.get(speaker));
+
+    screen.current_characters.speaker = speaker
+        .as_deref()
+        .filter(|_| config.stage.highlight_speaker)
+        .map(|speaker| config.sprite_name(speaker).to_owned());
+
+    let markup = Markup::parse(content);
+    let ui = &config.ui;
+    let text_color = character.map_or(graphics::WHITE, |c| c.text_color);
+    let mut text = graphics::Text::default();
+    for c in &markup.chars {
+        let mut fragment = graphics::TextFragment::new(c.c).color(graphics::Color {
+            a: 0.0,
+            ..c.color.unwrap_or(text_color)
+        });
+        // Fonts are only used if the game provides them
+        let font = match (c.bold, c.italic) {
+            (true, _) => ui.bold_font,
+            (false, true) => ui.italic_font,
+            (false, false) => None,
+        };
+        match font {
+            Some(font) => fragment = fragment.font(font),
+            None if c.bold => MISSING_BOLD_FONT.call_once(|| {
+                warn!("{{b}} is used but engine.ini [UI] has no bold_font, it won't be bold")
+            }),
+            None if c.italic => MISSING_ITALIC_FONT.call_once(|| {
+                warn!("{{i}} is used but engine.ini [UI] has no italic_font, it won't be italic")
+            }),
+            None => {}
+        }
+        text.add(fragment);
+    }
+
+    let layout = match &mut screen.nvl {
+        Some(nvl) => {
+            // Laying the line out can start a new page, which would forget it
+            let layout = nvl_layout(ctx, nvl, speaker.is_some(), &mut text);
+            nvl.current = Some(SavedLine {
+                speaker: speaker.clone(),
+                content: content.to_owned(),
+            });
+            layout
+        }
+        None => adv_layout(
+            ctx,
+            &screen.current_characters,
+            resources,
+            speaker,
+            character,
+            &mut text,
+        ),
+    };
+
+    let speaker_text = speaker.as_ref().map(|speaker| {
+        let mut speaker_text = graphics::Text::new(config.display_name(speaker));
+        speaker_text.set_bounds([f32::INFINITY, f32::INFINITY], graphics::Align::Left);
+        Sprite {
+            content: speaker_text,
+            param: DrawParam::new()
+                .dest(layout.speaker)
+                .color(character.map_or(graphics::WHITE, |c| c.color)),
+        }
+    });
+
+    let reveal_times = markup.reveal_times(config.user.borrow().text_speed);
+    let text = RichText {
+        formatting: markup.formatting,
+        text,
+    };
+    let text_tween = Tweener::new(text, move |rich_text, time, _| {
+        let text = &mut rich_text.text;
         let frag_count = text.fragments().len();
 
-        let lim = (time * cps) as usize;
+        let lim = reveal_times.iter().take_while(|&&t| t <= time).count();
         let lim = if lim > frag_count { frag_count } else { lim };
 
         for i in 0..lim {
@@ -83,19 +250,17 @@ pub fn load_text(
         lim == frag_count
     });
 
-    let text_params =
-        (Position::TopLeft.add_in_from(&layer_bounds, glam::Vec2::new(15.0, 55.0)),).into();
-
     screen.action = Action::Text(Box::new(TextBox {
-        layer: Sprite {
-            content: layer_image,
-            param: layer_params,
-        },
+        layer: layout.layer,
+        portrait: layout.portrait,
         speaker: speaker_text,
         content: Sprite {
             content: Box::new(text_tween),
-            param: text_params,
+            param: (layout.content,).into(),
         },
+        no_wait: markup.no_wait,
+        voice: character.and_then(|c| c.voice.clone()),
+        blipped: 0,
     }));
 
     Ok(())
@@ -78,3 +78,63 @@ pub fn points_to_rect(a: glam::Vec2, b: glam::Vec2) -> graphics::Rect {
         h: b.y - a.y,
     }
 }
+
+/// Parses a `rrggbb` color, optionally prefixed with `#`.
+pub fn parse_hex_color(s: &str) -> Result<graphics::Color, String> {
+    let hex = s.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return Err(format!("`{}` is not a color, expected rrggbb", s));
+    }
+    u32::from_str_radix(hex, 16)
+        .map(graphics::Color::from_rgb_u32)
+        .map_err(|_| format!("`{}` is not a color, expected rrggbb", s))
+}
+
+/// Parses an `x,y` pair.
+pub fn parse_vec2(s: &str) -> Result<glam::Vec2, String> {
+    let mut split = s.split(',').map(|n| n.trim().parse::<f32>());
+    match (split.next(), split.next(), split.next()) {
+        (Some(Ok(x)), Some(Ok(y)), None) => Ok(glam::Vec2::new(x, y)),
+        _ => Err(format!("`{}` is not a position, expected x,y", s)),
+    }
+}
+
+/// Formats a unix timestamp as `YYYY-MM-DD HH:MM UTC`, labelled since it isn't local time.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let secs = timestamp % 86400;
+
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        secs / 3600,
+        (secs % 3600) / 60
+    )
+}
+
+/// Formats a duration in seconds as `H:MM:SS`.
+pub fn format_playtime(playtime: f32) -> String {
+    let secs = playtime as u64;
+    format!("{}:{:02}:{:02}", secs / 3600, (secs % 3600) / 60, secs % 60)
+}
+
+#[test]
+fn test_format_timestamp() {
+    assert_eq!(format_timestamp(0), "1970-01-01 00:00 UTC");
+    assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00 UTC");
+    assert_eq!(format_timestamp(1_600_000_000), "2020-09-13 12:26 UTC");
+    assert_eq!(format_playtime(3725.5), "1:02:05");
+}
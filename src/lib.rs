@@ -1,6 +1,9 @@
-use std::{cell::RefCell, io::Read, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, io::Read, rc::Rc};
 
-use config::{CharacterConfig, Config, UIConfig, UserConfig};
+use config::{
+    AudioConfig, BackgroundConfig, CharacterConfig, Config, GlossaryEntry, ParticlePreset,
+    ReadHistory, StageConfig, TrackConfig, UIConfig, UserConfig,
+};
 use ggez::event;
 use ggez::{
     conf::{WindowMode, WindowSetup},
@@ -10,13 +13,20 @@ use log::error;
 use resource_manager::ResourceManager;
 use states::{splash::SplashState, State, StateManager};
 
+mod audio;
+mod command;
 mod config;
 mod containers;
 mod draw;
 mod helpers;
+mod markup;
 mod node;
+mod placement;
 mod resource_manager;
+mod rollback;
+mod save;
 mod states;
+mod transition;
 mod tween;
 
 pub fn run(resource_data: Option<Vec<u8>>) -> ggez::GameResult {
@@ -71,60 +81,151 @@ pub fn run(resource_data: Option<Vec<u8>>) -> ggez::GameResult {
     let user_config = if ggez::filesystem::exists(&ctx, &path) {
         println!("Loading user config");
         let file = ggez::filesystem::open(&mut ctx, path).unwrap();
-        serde_json::from_reader(file).unwrap()
+        serde_json::from_reader::<_, UserConfig>(file)
+            .unwrap()
+            .sanitize()
     } else {
         let user_config = UserConfig::default();
         user_config.update_data(&mut ctx, short_game_name);
         user_config
     };
 
+    let ui_color = |key: &str| {
+        ui_config
+            .get(key)
+            .map(|s| {
+                helpers::parse_hex_color(s).map_err(|e| {
+                    ggez::GameError::ConfigError(format!("engine.ini [UI] {}: {}", key, e))
+                })
+            })
+            .transpose()
+            .map(|color| color.unwrap_or_else(|| graphics::Color::from_rgb_u32(0)))
+    };
+
     let config = Config {
         short_game_name: short_game_name.to_owned(),
         characters: char_config
-            .into_iter()
+            .iter()
             .map(|(name, m)| {
-                (
-                    name.expect("No support for nameless characters").to_owned(),
-                    CharacterConfig {
-                        color: graphics::Color::from_rgb_u32(
-                            m.get("color")
-                                .map(|s| u32::from_str_radix(s, 16).unwrap())
-                                .unwrap_or_default(),
-                        ),
-                    },
-                )
+                let name = name.expect("No support for nameless characters");
+                Ok((name.to_owned(), CharacterConfig::parse(name, m)?))
             })
-            .collect(),
+            .collect::<ggez::GameResult<_>>()?,
+        backgrounds: if ggez::filesystem::exists(&ctx, "/backgrounds.ini") {
+            let mut backgrounds_file = ggez::filesystem::open(&mut ctx, "/backgrounds.ini")?;
+            ini::Ini::read_from(&mut backgrounds_file)
+                .map_err(|e| ggez::GameError::ConfigError(format!("backgrounds.ini: {}", e)))?
+                .iter()
+                .filter_map(|(name, m)| Some((name?, m)))
+                .map(|(name, m)| Ok((name.to_owned(), BackgroundConfig::parse(name, m)?)))
+                .collect::<ggez::GameResult<_>>()?
+        } else {
+            HashMap::new()
+        },
+        particles: {
+            let mut particles = ["snow", "rain", "petals", "dust"]
+                .iter()
+                .map(|&name| (name.to_owned(), ParticlePreset::builtin(name).unwrap()))
+                .collect::<HashMap<_, _>>();
+            if ggez::filesystem::exists(&ctx, "/particles.ini") {
+                let mut particles_file = ggez::filesystem::open(&mut ctx, "/particles.ini")?;
+                let particles_config = ini::Ini::read_from(&mut particles_file)
+                    .map_err(|e| ggez::GameError::ConfigError(format!("particles.ini: {}", e)))?;
+                for (name, m) in particles_config.iter() {
+                    // The weather command lowercases the name
+                    if let Some(name) = name {
+                        let name = name.to_lowercase();
+                        let preset = ParticlePreset::parse(&name, m)?;
+                        particles.insert(name, preset);
+                    }
+                }
+            }
+            particles
+        },
         credits: {
             let mut content = String::new();
             ggez::filesystem::open(&mut ctx, "/credits.txt")?.read_to_string(&mut content)?;
             content
         },
+        glossary: if ggez::filesystem::exists(&ctx, "/glossary.ini") {
+            let mut glossary_file = ggez::filesystem::open(&mut ctx, "/glossary.ini")?;
+            ini::Ini::read_from(&mut glossary_file)
+                .map_err(|e| ggez::GameError::ConfigError(format!("glossary.ini: {}", e)))?
+                .into_iter()
+                .filter_map(|(key, m)| {
+                    let key = key?.to_owned();
+                    let entry = GlossaryEntry {
+                        name: m.get("name").unwrap_or(&key).to_owned(),
+                        definition: m.get("definition").unwrap_or_default().to_owned(),
+                    };
+                    Some((key, entry))
+                })
+                .collect()
+        } else {
+            HashMap::new()
+        },
         ui: UIConfig {
             title: ui_config
                 .get("title")
                 .map(|s| s.to_owned())
                 .unwrap_or_else(|| "Untitled game".to_string()),
-            button_color: graphics::Color::from_rgb_u32(
-                ui_config
-                    .get("button_color")
-                    .map(|s| u32::from_str_radix(s, 16).unwrap())
-                    .unwrap_or_default(),
-            ),
-            button_pressed_color: graphics::Color::from_rgb_u32(
-                ui_config
-                    .get("button_pressed_color")
-                    .map(|s| u32::from_str_radix(s, 16).unwrap())
-                    .unwrap_or_default(),
-            ),
-            button_highlight_color: graphics::Color::from_rgb_u32(
-                ui_config
-                    .get("button_highlight_color")
-                    .map(|s| u32::from_str_radix(s, 16).unwrap())
-                    .unwrap_or_default(),
-            ),
+            button_color: ui_color("button_color")?,
+            button_pressed_color: ui_color("button_pressed_color")?,
+            button_highlight_color: ui_color("button_highlight_color")?,
+            bold_font: ui_config
+                .get("bold_font")
+                .map(|path| graphics::Font::new(&mut ctx, path))
+                .transpose()?,
+            italic_font: ui_config
+                .get("italic_font")
+                .map(|path| graphics::Font::new(&mut ctx, path))
+                .transpose()?,
+        },
+        stage: {
+            let mut stage = StageConfig::default();
+            if let Some(stage_config) = engine_config.section(Some("Stage")) {
+                if let Some(fade) = stage_config.get("expression_fade") {
+                    stage.expression_fade = fade
+                        .trim_end_matches('s')
+                        .parse()
+                        .ok()
+                        .filter(|fade: &f32| fade.is_finite() && *fade >= 0.0)
+                        .ok_or_else(|| {
+                            ggez::GameError::ConfigError(format!(
+                                "engine.ini [Stage] expression_fade: `{}` is not a duration",
+                                fade
+                            ))
+                        })?;
+                }
+                if let Some(highlight) = stage_config.get("highlight_speaker") {
+                    stage.highlight_speaker = highlight.parse().map_err(|_| {
+                        ggez::GameError::ConfigError(format!(
+                            "engine.ini [Stage] highlight_speaker: `{}` is not true or false",
+                            highlight
+                        ))
+                    })?;
+                }
+            }
+            stage
+        },
+        audio: engine_config
+            .section(Some("Audio"))
+            .map(AudioConfig::parse)
+            .transpose()?
+            .unwrap_or_default(),
+        tracks: if ggez::filesystem::exists(&ctx, "/audio.ini") {
+            let mut tracks_file = ggez::filesystem::open(&mut ctx, "/audio.ini")?;
+            ini::Ini::read_from(&mut tracks_file)
+                .map_err(|e| ggez::GameError::ConfigError(format!("audio.ini: {}", e)))?
+                .iter()
+                .filter_map(|(name, m)| Some((name?, m)))
+                .map(|(name, m)| Ok((name.to_owned(), TrackConfig::parse(name, m)?)))
+                .collect::<ggez::GameResult<_>>()?
+        } else {
+            HashMap::new()
         },
         user: Rc::new(RefCell::new(user_config)),
+        read: Rc::new(RefCell::new(ReadHistory::load(&mut ctx, short_game_name))),
     };
 
     let resources = Box::leak(Box::new(ResourceManager::new(config)));
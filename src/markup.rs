@@ -0,0 +1,240 @@
+use ggez::graphics::Color;
+
+use crate::{
+    containers::rich_text::{Format, FormatEntry, LINK_COLOR},
+    helpers::parse_hex_color,
+};
+
+/// A single character of dialogue with the formatting applied to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkupChar {
+    pub c: char,
+    pub color: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    // Seconds the typewriter waits before showing this character
+    pub pause: f32,
+    // Overrides the user's text speed
+    pub cps: Option<f32>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Markup {
+    pub chars: Vec<MarkupChar>,
+    pub formatting: Vec<FormatEntry>,
+    // Continue to the next line as soon as this one has been shown
+    pub no_wait: bool,
+}
+
+impl Markup {
+    /// Parses inline tags in a dialogue line.
+    ///
+    /// Supported tags are `{color=#rrggbb}..{/color}`, `{b}..{/b}`, `{i}..{/i}`,
+    /// `{cps=20}..{/cps}`, `{w=0.5}` and `{nw}`, `{{` is a literal `{`.
+    /// Unknown tags are shown as is.
+    ///
+    /// Links are written `[text](url)` or `[text](glossary:key)`, the credits use them too.
+    pub fn parse(content: &str) -> Self {
+        let mut markup = Markup::default();
+
+        let mut colors: Vec<Color> = Vec::new();
+        let mut speeds: Vec<f32> = Vec::new();
+        let mut bold = 0;
+        let mut italic = 0;
+        let mut pause = 0.0;
+        // Glyph the link starts at, its target and the length of the content left at its `](`
+        let mut link: Option<(usize, &str, usize)> = None;
+
+        // Characters pushed so far, not counting newlines
+        let mut glyph_count = 0;
+
+        let mut rest = content;
+        while let Some(c) = rest.chars().next() {
+            if let Some((start, target, end_len)) = link {
+                if rest.len() == end_len {
+                    if start < glyph_count {
+                        markup.formatting.push(FormatEntry {
+                            format: Format::from_target(target.to_owned()),
+                            start,
+                            end: glyph_count,
+                        });
+                    }
+                    link = None;
+                    rest = &rest[target.len() + 3..]; // ]() is 3 characters
+                    continue;
+                }
+            } else if c == '[' {
+                if let Some((text_len, target)) = parse_link(rest) {
+                    link = Some((glyph_count, target, rest.len() - text_len - 1));
+                    rest = &rest[1..];
+                    continue;
+                }
+            }
+
+            if c == '{' && rest[1..].starts_with('{') {
+                rest = &rest[2..];
+            } else if let Some(end) = rest.find('}').filter(|_| c == '{') {
+                let tag = &rest[1..end];
+                let (name, value) = match tag.find('=') {
+                    Some(n) => (&tag[..n], Some(&tag[n + 1..])),
+                    None => (tag, None),
+                };
+                let known = match (name, value) {
+                    ("color", Some(value)) => parse_hex_color(value)
+                        .map(|color| colors.push(color))
+                        .is_ok(),
+                    ("/color", None) => colors.pop().is_some(),
+                    ("b", None) => {
+                        bold += 1;
+                        true
+                    }
+                    // Closing tags without an opening one are shown as is
+                    ("/b", None) if bold > 0 => {
+                        bold -= 1;
+                        true
+                    }
+                    ("i", None) => {
+                        italic += 1;
+                        true
+                    }
+                    ("/i", None) if italic > 0 => {
+                        italic -= 1;
+                        true
+                    }
+                    // Speeds and pauses that would keep the line from finishing are unknown
+                    ("cps", Some(value)) => value
+                        .parse()
+                        .ok()
+                        .filter(|cps: &f32| cps.is_finite() && *cps > 0.0)
+                        .map(|cps| speeds.push(cps))
+                        .is_some(),
+                    ("/cps", None) => speeds.pop().is_some(),
+                    ("w", Some(value)) => value
+                        .parse()
+                        .ok()
+                        .filter(|w: &f32| w.is_finite() && *w >= 0.0)
+                        .map(|w| pause += w)
+                        .is_some(),
+                    ("nw", None) => {
+                        markup.no_wait = true;
+                        true
+                    }
+                    _ => false,
+                };
+                if known {
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+                rest = &rest[1..];
+            } else {
+                rest = &rest[c.len_utf8()..];
+            }
+            if c != '\n' {
+                glyph_count += 1;
+            }
+            markup.chars.push(MarkupChar {
+                c,
+                color: colors.last().copied().or_else(|| link.map(|_| LINK_COLOR)),
+                bold: bold > 0,
+                italic: italic > 0,
+                pause,
+                cps: speeds.last().copied(),
+            });
+            pause = 0.0;
+        }
+
+        markup
+    }
+
+    /// The line without any tags.
+    pub fn plain(&self) -> String {
+        self.chars.iter().map(|c| c.c).collect()
+    }
+
+    /// The time at which each character should be shown by the typewriter.
+    pub fn reveal_times(&self, cps: f32) -> Vec<f32> {
+        let mut time = 0.0;
+        self.chars
+            .iter()
+            .map(|c| {
+                time += c.pause + 1.0 / c.cps.unwrap_or(cps);
+                time
+            })
+            .collect()
+    }
+}
+
+/// Returns the length of the text and the target of a link at the start of `s`.
+fn parse_link(s: &str) -> Option<(usize, &str)> {
+    // The text ends at the first `]`, which has to be followed by the target
+    let text_end = s.find(']')?;
+    if !s[text_end + 1..].starts_with('(') {
+        return None;
+    }
+    let target_len = s[text_end + 2..].find(')')?;
+    Some((text_end - 1, &s[text_end + 2..text_end + 2 + target_len]))
+}
+
+#[test]
+fn test_markup() {
+    let markup = Markup::parse("a{b}b{/b}{w=0.5}{color=#ff0000}{cps=4}c{/cps}{/color}{{{x}{nw}");
+    assert_eq!(markup.plain(), "abc{{x}");
+    assert!(markup.no_wait);
+    assert!(!markup.chars[0].bold);
+    assert!(markup.chars[1].bold);
+    assert_eq!(markup.chars[2].color, Some(Color::from_rgb_u32(0xFF_00_00)));
+    assert_eq!(markup.chars[2].pause, 0.5);
+    assert_eq!(markup.chars[3].color, None);
+    assert_eq!(
+        markup.reveal_times(1.0),
+        vec![1.0, 2.0, 2.75, 3.75, 4.75, 5.75, 6.75]
+    );
+
+    let markup =
+        Markup::parse("See\n[the {b}tower{/b}](glossary:tower) or [x](https://example.com)");
+    assert_eq!(markup.plain(), "See\nthe tower or x");
+    assert_eq!(markup.chars[4].color, Some(LINK_COLOR));
+    assert_eq!(
+        markup.formatting,
+        vec![
+            FormatEntry {
+                format: Format::Glossary("tower".to_owned()),
+                start: 3,
+                end: 12,
+            },
+            FormatEntry {
+                format: Format::Link("https://example.com".to_owned()),
+                start: 16,
+                end: 17,
+            },
+        ]
+    );
+
+    // Values that would stop the line from ever finishing are shown as text
+    for tag in &[
+        "{cps=0}",
+        "{cps=-5}",
+        "{cps=NaN}",
+        "{cps=inf}",
+        "{w=inf}",
+        "{w=-1}",
+    ] {
+        let markup = Markup::parse(&format!("a{}b", tag));
+        assert_eq!(markup.plain(), format!("a{}b", tag));
+        assert!(markup.reveal_times(10.0).iter().all(|t| t.is_finite()));
+    }
+    let markup = Markup::parse("a{/b}{/i}b");
+    assert_eq!(markup.plain(), "a{/b}{/i}b");
+    assert!(markup.chars.iter().all(|c| !c.bold && !c.italic));
+
+    let markup = Markup::parse("[sigh] see [x](y)");
+    assert_eq!(markup.plain(), "[sigh] see x");
+    assert_eq!(
+        markup.formatting,
+        vec![FormatEntry {
+            format: Format::from_target("y".to_owned()),
+            start: 11,
+            end: 12,
+        }]
+    );
+}
@@ -1,40 +1,236 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
 
-use ggez::Context;
+use ggez::{
+    graphics::{self, Rect},
+    Context,
+};
+use log::warn;
 use novelscript::SceneNodeLoad;
 
 use crate::{
-    containers::{background::BackgroundContainer, gamescreen::GameScreen},
+    audio::Track,
+    command::Command,
+    config::Animation,
+    containers::{
+        background::BackgroundContainer, camera::CameraView, filter::Grade, gamescreen::GameScreen,
+        nvl::NvlPage, particles::ParticleLayer,
+    },
     containers::{button::Button, gamescreen::Action, stackcontainer::StackContainer},
     draw::load_text,
     helpers::Position,
+    placement::{Placement, XPosition},
     resource_manager::ResourceManager,
-    states::game::Character,
-    states::game::{Background, Placement},
+    save::SavedWeather,
+    states::game::{Audio, Background, BackgroundLayer},
+    states::game::{Character, CharacterLayer},
+    transition::{
+        parse_remove, parse_scene, rule_alpha, smoothstep, BackgroundTransition,
+        CharacterDirections, Direction, Motion, Transition,
+    },
     tween::TargetTweener,
     tween::TransitionTweener,
+    tween::Tween,
 };
 
-pub fn load_character_tween(
+/// `layer_values` is only used if the character is layered, see [`layer_values`].
+pub fn load_character(
     ctx: &mut Context,
     resources: &'static ResourceManager,
     name: String,
     expression: String,
+    layer_values: BTreeMap<String, String>,
     placement: Placement,
-) -> ggez::GameResult<TargetTweener<Character, impl Fn(&mut Character, f32)>> {
-    Ok(TargetTweener::new(
-        0.75,
-        Character {
-            alpha: 0.0,
-            image: resources.get_image(ctx, &format!("/char/{}/{}.png", name, expression)),
-            name,
-            expression,
-            position: placement,
-        },
-        |cur: &mut Character, progress| {
-            cur.alpha = progress;
+) -> ggez::GameResult<Character> {
+    let config = resources.get_config();
+    let sprite = config.sprite_name(&name).to_owned();
+    let layers = match config
+        .characters
+        .get(&name)
+        .filter(|config| config.is_layered())
+    {
+        Some(config) => config
+            .layers
+            .iter()
+            .filter_map(|layer| {
+                let value = layer_values.get(&layer.name)?;
+                let path = format!("/char/{}/{}/{}.png", sprite, layer.name, value);
+                Some(
+                    resources
+                        .try_get_image(ctx, &path)
+                        .map(|image| CharacterLayer {
+                            image,
+                            path,
+                            offset: layer.offset,
+                        }),
+                )
+            })
+            .collect::<ggez::GameResult<_>>()?,
+        None => {
+            let path = format!("/char/{}/{}.png", sprite, expression);
+            vec![CharacterLayer {
+                image: resources.try_get_image(ctx, &path)?,
+                path,
+                offset: glam::Vec2::zero(),
+            }]
+        }
+    };
+    Ok(Character {
+        alpha: 1.0,
+        layers,
+        layer_values,
+        previous_layers: None,
+        swap: 1.0,
+        name,
+        sprite,
+        expression,
+        position: placement,
+        offset: glam::Vec2::zero(),
+        exit: Transition::Fade,
+        dim: 0.0,
+    })
+}
+
+pub fn character_tween(
+    character: Character,
+    motion: Motion,
+) -> TargetTweener<Character, impl Fn(&mut Character, f32)> {
+    let mut tween = TargetTweener::new(
+        motion.duration,
+        character,
+        move |cur: &mut Character, progress| {
+            let (alpha, offset) = motion.apply(progress);
+            cur.alpha = alpha;
+            cur.offset = offset;
+            cur.swap = motion.swap_progress(progress);
+            if cur.swap >= 1.0 {
+                cur.previous_layers = None;
+            }
         },
-    ))
+    );
+    // Start at the beginning of the motion instead of where the character was created
+    tween.update(0.0);
+    tween
+}
+
+/// The images shown by each layer of `name` after applying `expression`,
+/// empty if the character isn't layered.
+pub fn layer_values(
+    resources: &'static ResourceManager,
+    name: &str,
+    current: Option<&BTreeMap<String, String>>,
+    expression: &str,
+) -> ggez::GameResult<BTreeMap<String, String>> {
+    match resources.get_config().characters.get(name) {
+        Some(config) if config.is_layered() => {
+            config.layer_values(current, expression).map_err(|e| {
+                ggez::GameError::ResourceLoadError(format!(
+                    "Invalid expression for {}: {}",
+                    name, e
+                ))
+            })
+        }
+        _ => Ok(BTreeMap::new()),
+    }
+}
+
+/// The layers of the background `name`, see [`BackgroundConfig`](crate::config::BackgroundConfig).
+pub fn load_background_layers(
+    ctx: &mut Context,
+    resources: &'static ResourceManager,
+    name: &str,
+) -> ggez::GameResult<Vec<BackgroundLayer>> {
+    resources
+        .get_config()
+        .background(name)
+        .layers
+        .into_iter()
+        .map(|layer| -> ggez::GameResult<BackgroundLayer> {
+            let frames = match layer.animation {
+                None => {
+                    let path = format!("/bg/{}.png", layer.path);
+                    vec![(
+                        path.clone(),
+                        resources.try_get_image(ctx, &path)?,
+                        Rect::one(),
+                    )]
+                }
+                Some(Animation::Frames { count, .. }) => (1..=count)
+                    .map(|n| -> ggez::GameResult<_> {
+                        let path = format!("/bg/{}/{:04}.png", layer.path, n);
+                        let image = resources.try_get_image(ctx, &path)?;
+                        Ok((path, image, Rect::one()))
+                    })
+                    .collect::<ggez::GameResult<_>>()?,
+                Some(Animation::Sheet {
+                    columns,
+                    rows,
+                    count,
+                    ..
+                }) => {
+                    let path = format!("/bg/{}.png", layer.path);
+                    let image = resources.try_get_image(ctx, &path)?;
+                    let (w, h) = (1.0 / columns as f32, 1.0 / rows as f32);
+                    (0..count)
+                        .map(|n| {
+                            let (column, row) = (n % columns, n / columns);
+                            (
+                                path.clone(),
+                                image.clone(),
+                                Rect::new(column as f32 * w, row as f32 * h, w, h),
+                            )
+                        })
+                        .collect()
+                }
+            };
+            Ok(BackgroundLayer {
+                frames,
+                fps: match layer.animation {
+                    Some(Animation::Frames { fps, .. }) | Some(Animation::Sheet { fps, .. }) => fps,
+                    None => 0.0,
+                },
+                parallax: layer.parallax,
+                scroll: layer.scroll,
+            })
+        })
+        .collect()
+}
+
+// How many images a rule image transition is split into
+const RULE_STEPS: usize = 8;
+// Widest the images are, they're only on screen during the transition
+const RULE_STEP_WIDTH: usize = 960;
+
+/// Precomputes `image` being revealed by the rule image `rule` in /transitions/,
+/// at a lower resolution if it's wider than [`RULE_STEP_WIDTH`].
+fn rule_steps(
+    ctx: &mut Context,
+    resources: &'static ResourceManager,
+    image: &graphics::Image,
+    rule: &str,
+) -> ggez::GameResult<Vec<graphics::Image>> {
+    let (image_width, image_height) = (image.width() as usize, image.height() as usize);
+    let width = image_width.min(RULE_STEP_WIDTH);
+    let height = (image_height * width / image_width).max(1);
+    let mask = resources.get_rule_mask(ctx, rule, width as u16, height as u16)?;
+    let image_pixels = image.to_rgba8(ctx)?;
+    let pixels = (0..width * height)
+        .flat_map(|i| {
+            let x = (i % width) * image_width / width;
+            let y = (i / width) * image_height / height;
+            let n = (y * image_width + x) * 4;
+            image_pixels[n..n + 4].iter().copied()
+        })
+        .collect::<Vec<_>>();
+    (0..RULE_STEPS)
+        .map(|step| {
+            let progress = step as f32 / (RULE_STEPS - 1) as f32;
+            let mut step_pixels = pixels.clone();
+            for (pixel, mask) in step_pixels.chunks_mut(4).zip(mask.iter()) {
+                pixel[3] = (pixel[3] as f32 * rule_alpha(*mask, progress)) as u8;
+            }
+            graphics::Image::from_rgba8(ctx, width as u16, height as u16, &step_pixels)
+        })
+        .collect()
 }
 
 pub fn load_background_tween(
@@ -42,6 +238,9 @@ pub fn load_background_tween(
     resources: &'static ResourceManager,
     prev: Option<Background>,
     name: String,
+    transition: BackgroundTransition,
+    duration: f32,
+    grade: Option<Grade>,
 ) -> ggez::GameResult<
     TransitionTweener<
         Background,
@@ -49,22 +248,77 @@ pub fn load_background_tween(
         impl Fn(&mut Option<Background>, &mut Background, f32),
     >,
 > {
-    let prev = prev.map(|mut n| {
-        n.fade = 0.0;
-        n
+    // Starts from wherever the previous transition was
+    let prev = prev.map(|n| Background {
+        fade: 1.0,
+        time: n.time,
+        ..Background::new(n.layers, n.name)
     });
+    let mut to = Background::new(load_background_layers(ctx, resources, &name)?, name);
+    let mut transition = transition;
+    if let BackgroundTransition::Rule(rule) = &transition {
+        match to.single_image() {
+            Some((path, image)) if prev.is_some() && duration > 0.0 => {
+                // The steps are drawn as they are, so they're graded beforehand
+                let image = match grade {
+                    Some(grade) => resources.get_graded_image(ctx, path, grade)?,
+                    None => image.clone(),
+                };
+                let steps = rule_steps(ctx, resources, &image, rule)?;
+                to.reveal = Some(Rc::new(steps));
+            }
+            Some(_) => {}
+            None => {
+                warn!(
+                    "Rule image transitions only work on still backgrounds, {} dissolves instead",
+                    to.name
+                );
+                transition = BackgroundTransition::Dissolve;
+            }
+        }
+    }
+    let size = crate::helpers::target_size();
     Ok(TransitionTweener::new(
         true,
-        0.5,
-        (
-            prev,
-            Background::new(resources.get_image(ctx, &format!("/bg/{}.png", name)), name),
-        ),
-        |prev: &mut Option<Background>, to: &mut Background, progress| {
-            if let Some(prev) = prev {
-                prev.fade = 1.0 / progress;
+        duration,
+        (prev, to),
+        move |prev: &mut Option<Background>, to: &mut Background, progress| {
+            to.fade = 1.0;
+            match &transition {
+                BackgroundTransition::Instant | BackgroundTransition::Dissolve => {
+                    to.fade = progress;
+                }
+                BackgroundTransition::FadeBlack => {
+                    if let Some(prev) = prev {
+                        prev.brightness = (1.0 - progress * 2.0).max(0.0);
+                    }
+                    to.brightness = (progress * 2.0 - 1.0).max(0.0);
+                    if progress < 0.5 {
+                        to.fade = 0.0;
+                    }
+                }
+                BackgroundTransition::Wipe(direction) => {
+                    to.clip = match direction {
+                        Direction::Right => Rect::new(0.0, 0.0, progress, 1.0),
+                        Direction::Left => Rect::new(1.0 - progress, 0.0, progress, 1.0),
+                        Direction::Down => Rect::new(0.0, 0.0, 1.0, progress),
+                        Direction::Up => Rect::new(0.0, 1.0 - progress, 1.0, progress),
+                    };
+                }
+                BackgroundTransition::Slide(direction) | BackgroundTransition::Push(direction) => {
+                    let moved = direction.vector() * size * smoothstep(progress);
+                    to.offset = moved - direction.vector() * size;
+                    if let (Some(prev), BackgroundTransition::Push(..)) = (prev, &transition) {
+                        prev.offset = moved;
+                    }
+                }
+                BackgroundTransition::Rule(..) => {
+                    to.fade = progress;
+                    if progress >= 1.0 {
+                        to.reveal = None;
+                    }
+                }
             }
-            to.fade = progress;
         },
     ))
 }
@@ -74,8 +328,7 @@ pub fn load_load_node(
     resources: &'static ResourceManager,
     screen: &mut GameScreen,
     node: SceneNodeLoad,
-    sfx: &mut Option<ggez::audio::Source>,
-    music: &mut Option<ggez::audio::Source>,
+    audio: &mut Audio,
 ) -> ggez::GameResult {
     if let novelscript::SceneNodeLoad::Character {
         character,
@@ -83,71 +336,201 @@ pub fn load_load_node(
         placement,
     } = node
     {
+        let directions = placement
+            .map(|s| {
+                CharacterDirections::parse(&s).map_err(|e| {
+                    ggez::GameError::ResourceLoadError(format!(
+                        "Invalid placement for {}: {}",
+                        character, e
+                    ))
+                })
+            })
+            .transpose()?
+            .unwrap_or_default();
         // TODO use this until a proper change character command is added to novelscript
-        if let Some(c) = screen
+        if let Some(n) = screen
             .current_characters
             .current
-            .iter_mut()
-            .find(|c| c.get_current().name == character)
+            .iter()
+            .position(|c| c.get_current().name == character)
         {
-            let current = c.get_current();
-            *c = Box::new(load_character_tween(
-                ctx,
-                resources,
-                character,
-                expression.unwrap_or(current.expression.clone()),
-                placement
-                    .map(|s| Placement::parse(s.to_lowercase().as_str()))
-                    .unwrap_or(current.position),
-            )?);
-            c.finish();
-        } else {
-            let tween = load_character_tween(
-                ctx,
+            let characters = &screen.current_characters;
+            let current = characters.current[n].get_current();
+            let placement = directions
+                .placement
+                .map_or(current.position, |change| change.apply(current.position));
+            // Slide from wherever the character is currently drawn
+            let from_x = if directions.animate_move.unwrap_or(true) {
+                characters.center_x(n, &current.position) + current.offset.x
+                    - characters.center_x(n, &placement)
+            } else {
+                0.0
+            };
+            let exit = directions.exit.unwrap_or(current.exit);
+            let expression = expression.unwrap_or_else(|| current.expression.clone());
+            let values = layer_values(
                 resources,
-                character,
-                expression.unwrap_or_else(|| "Normal".to_owned()),
-                placement
-                    .map(|s| Placement::parse(s.to_lowercase().as_str()))
-                    .unwrap_or(Placement::Left),
+                &character,
+                Some(&current.layer_values),
+                &expression,
             )?;
-            screen.current_characters.current.insert(
-                match tween.current.position {
-                    Placement::Left => 0,
-                    Placement::Right => screen.current_characters.current.len(),
-                },
-                Box::new(tween),
+            let changed = expression != current.expression || values != current.layer_values;
+            let swap = if changed && directions.animate_swap.unwrap_or(true) {
+                resources.get_config().stage.expression_fade
+            } else {
+                0.0
+            };
+            let previous_layers = current.layers.clone();
+            let dim = current.dim;
+            let mut new = load_character(ctx, resources, character, expression, values, placement)?;
+            new.exit = exit;
+            new.dim = dim;
+            if swap > 0.0 {
+                new.previous_layers = Some(previous_layers);
+            }
+            screen.current_characters.current[n] = Box::new(character_tween(
+                new,
+                Motion::stay(from_x, directions.effect, swap),
+            ));
+        } else {
+            let config = resources.get_config();
+            let character_config = config.characters.get(&character);
+            let expression = expression
+                .or_else(|| character_config.and_then(|c| c.default_expression.clone()))
+                .unwrap_or_else(|| {
+                    // Layered characters start with their default layers
+                    let is_layered = matches!(character_config, Some(c) if c.is_layered());
+                    if is_layered { "" } else { "Normal" }.to_owned()
+                });
+            let placement = character_config
+                .and_then(|c| c.default_placement)
+                .unwrap_or_default();
+            let placement = directions
+                .placement
+                .map_or(placement, |change| change.apply(placement));
+            let values = layer_values(resources, &character, None, &expression)?;
+            let mut new = load_character(ctx, resources, character, expression, values, placement)?;
+            new.exit = directions.exit.unwrap_or(Transition::Fade);
+            let characters = &screen.current_characters;
+            let motion = Motion::enter(
+                directions.enter.unwrap_or(Transition::Fade),
+                characters.center_x(characters.current.len(), &new.position),
+                new.size().x,
             );
+            screen
+                .current_characters
+                .current
+                .push(Box::new(character_tween(new, motion)));
         }
     } else if let novelscript::SceneNodeLoad::Background { name } = node {
+        let (name, transition, duration) = parse_scene(&name).map_err(|e| {
+            ggez::GameError::ResourceLoadError(format!("Invalid scene {}: {}", name, e))
+        })?;
         let prev = screen
             .current_background
             .take()
             .map(|n| n.current.take_final_box().1);
         screen.current_background = Some(BackgroundContainer {
-            current: Box::new(load_background_tween(ctx, resources, prev, name)?),
+            current: Box::new(load_background_tween(
+                ctx,
+                resources,
+                prev,
+                name.to_owned(),
+                transition,
+                duration,
+                screen.filter.target.grade,
+            )?),
         });
     } else if let novelscript::SceneNodeLoad::PlaySound { name, channel } = node {
-        let src = match channel.as_str() {
-            "sfx" => sfx,
-            "music" => music,
-            _ => panic!("invalid channel `{}` for sound `{}`", channel, name),
-        };
-        println!("Loading {} {}", name, channel);
-        src.replace(resources.get_sound_source(ctx, &format!("/audio/{}", name)));
+        play_sound(ctx, resources, audio, name, &channel, None, None)?;
     } else if let novelscript::SceneNodeLoad::RemoveCharacter { name } = node {
+        let (name, transition) = parse_remove(&name).map_err(|e| {
+            ggez::GameError::ResourceLoadError(format!("Invalid removal of {}: {}", name, e))
+        })?;
         if let Some(idx) = screen
             .current_characters
             .current
             .iter()
             .position(|c| c.get_current().name == name)
         {
-            screen.current_characters.current.remove(idx);
+            let characters = &mut screen.current_characters;
+            let center_x =
+                characters.center_x(idx, &characters.current[idx].get_current().position);
+            let tween = characters.current.remove(idx);
+            // It may still be entering, the exit continues from where the entrance got to
+            let (alpha, offset_x) = {
+                let current = tween.get_current();
+                (current.alpha, current.offset.x)
+            };
+            let mut character = tween.take_final_box();
+            character.offset = glam::Vec2::zero();
+            // The others can move into its place while it leaves
+            character.position.x = XPosition::Pixels(center_x);
+            let motion = Motion::exit(
+                transition.unwrap_or(character.exit),
+                center_x,
+                character.size().x,
+            )
+            .starting_from(alpha, offset_x);
+            screen
+                .current_characters
+                .leaving
+                .push(Box::new(character_tween(character, motion)));
         }
     }
     Ok(())
 }
 
+/// Plays `name` on `channel`, replacing what was playing. Unless they're given, whether the
+/// sound loops comes from audio.ini or the channel and looping sounds crossfade. Sounds on
+/// channels missing from engine.ini are skipped.
+pub fn play_sound(
+    ctx: &mut Context,
+    resources: &'static ResourceManager,
+    audio: &mut Audio,
+    name: String,
+    channel: &str,
+    looping: Option<bool>,
+    fade: Option<f32>,
+) -> ggez::GameResult {
+    let config = resources.get_config();
+    // Channel names in engine.ini are lowercase
+    let channel = channel.to_lowercase();
+    let channel = channel.as_str();
+    if !config.audio.has_channel(channel) {
+        warn!(
+            "Skipping sound `{}` on unknown channel `{}`, channels can be added in engine.ini [Audio]",
+            name, channel
+        );
+        return Ok(());
+    }
+    let track_config = config.tracks.get(&name).cloned().unwrap_or_default();
+    let looping = looping
+        .or(track_config.looping)
+        .unwrap_or_else(|| config.audio.looping.iter().any(|c| c == channel));
+    let fade = fade.unwrap_or_else(|| {
+        if looping && audio.channels.contains_key(channel) {
+            config.audio.crossfade
+        } else {
+            0.0
+        }
+    });
+    audio.stop(channel, fade);
+    println!("Loading {} {}", name, channel);
+    let data = resources.get_sound(ctx, &format!("/audio/{}", name))?;
+    let track = Track::new(
+        ctx,
+        name,
+        channel.to_owned(),
+        data,
+        &track_config,
+        looping,
+        fade,
+    )?;
+    audio.channels.insert(channel.to_owned(), track);
+    Ok(())
+}
+
 pub fn load_data_node(
     ctx: &mut Context,
     screen: &mut GameScreen,
@@ -180,7 +563,91 @@ pub fn load_data_node(
                 n as u32,
             ))
         }
+        screen.keep_nvl_line();
         screen.action = Action::Choice(stack);
     }
     Ok(())
 }
+
+pub fn run_command(
+    ctx: &mut Context,
+    screen: &mut GameScreen,
+    resources: &'static ResourceManager,
+    audio: &mut Audio,
+    command: Command,
+) -> ggez::GameResult {
+    match command {
+        Command::Nvl => {
+            if screen.nvl.is_none() {
+                screen.action = Action::None;
+                screen.nvl = Some(NvlPage::new(ctx)?);
+            }
+        }
+        Command::Adv => {
+            if screen.nvl.take().is_some() {
+                screen.action = Action::None;
+            }
+        }
+        Command::Clear => {
+            if let Some(nvl) = &mut screen.nvl {
+                nvl.clear();
+                screen.action = Action::None;
+            }
+        }
+        Command::Camera {
+            x,
+            y,
+            zoom,
+            duration,
+        } => {
+            let target = screen.camera.target;
+            screen.camera.move_to(
+                CameraView {
+                    x: x.unwrap_or(target.x),
+                    y: y.unwrap_or(target.y),
+                    zoom: zoom.unwrap_or(target.zoom),
+                },
+                duration,
+            );
+        }
+        Command::Shake { strength, duration } => screen.camera.shake(strength, duration),
+        Command::Flash { color, duration } => screen.camera.flash(color, duration),
+        Command::Play {
+            name,
+            channel,
+            looping,
+            fade,
+        } => play_sound(ctx, resources, audio, name, &channel, looping, fade)?,
+        Command::Stop { channel, duration } => audio.stop(&channel, duration),
+        Command::Filter { grade, duration } => screen.filter.set_grade(grade, duration),
+        Command::Vignette {
+            strength,
+            duration,
+            over_ui,
+        } => screen.filter.set_vignette(strength, over_ui, duration),
+        Command::Fade {
+            color,
+            duration,
+            over_ui,
+        } => screen.filter.set_fade(color, over_ui, duration),
+        Command::Weather { name, front, seed } => {
+            screen.weather = match name {
+                Some(name) => {
+                    // Without a seed it looks different every time, but the seed is still saved
+                    let seed = seed.unwrap_or_else(|| {
+                        std::time::SystemTime::now()
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .map_or(0, |time| time.as_nanos() as u64)
+                    });
+                    Some(ParticleLayer::new(
+                        ctx,
+                        resources,
+                        SavedWeather { name, seed, front },
+                    )?)
+                }
+                None => None,
+            };
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+/// Named horizontal positions on the stage.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Anchor {
+    FarLeft,
+    Left,
+    Center,
+    Right,
+    FarRight,
+}
+
+impl Anchor {
+    /// Fraction of the screen width the character is centered on.
+    pub fn fraction(&self) -> f32 {
+        match self {
+            Anchor::FarLeft => 0.15,
+            Anchor::Left => 0.3,
+            Anchor::Center => 0.5,
+            Anchor::Right => 0.7,
+            Anchor::FarRight => 0.85,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum XPosition {
+    // Spaced evenly with the other automatically placed characters, in the order they entered
+    Auto,
+    Anchor(Anchor),
+    Percent(f32),
+    Pixels(f32),
+}
+
+/// Where and how big a character is drawn.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Placement {
+    pub x: XPosition,
+    pub scale: f32,
+    // Pixels, positive moves the character down
+    pub y_offset: f32,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Self {
+            x: XPosition::Auto,
+            scale: 1.0,
+            y_offset: 0.0,
+        }
+    }
+}
+
+/// The parts of a [`Placement`] a character command sets, the rest are kept.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct PlacementChange {
+    pub x: Option<XPosition>,
+    pub scale: Option<f32>,
+    pub y_offset: Option<f32>,
+}
+
+impl PlacementChange {
+    /// Parses the placement argument of a character command, e.g. `far-left`, `40%`,
+    /// `300px` or `right scale=1.2 y=-20`. Tokens can be separated by spaces or commas.
+    pub fn parse(v: &str) -> Result<Self, String> {
+        let mut change = PlacementChange::default();
+        for token in v
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|s| !s.is_empty())
+        {
+            let token = token.to_lowercase();
+            if let Some(anchor) = parse_anchor(&token) {
+                change.x = Some(XPosition::Anchor(anchor));
+            } else if token == "auto" {
+                change.x = Some(XPosition::Auto);
+            } else if let Some(percent) = token.strip_suffix('%') {
+                change.x = Some(XPosition::Percent(parse_number(percent)?));
+            } else if let Some(pixels) = token.strip_suffix("px") {
+                change.x = Some(XPosition::Pixels(parse_number(pixels)?));
+            } else if let Some(scale) = token.strip_prefix("scale=") {
+                let scale = parse_number(scale)?;
+                if scale <= 0.0 {
+                    return Err(format!("scale `{}` is not above 0", scale));
+                }
+                change.scale = Some(scale);
+            } else if let Some(y) = token.strip_prefix("y=") {
+                change.y_offset = Some(parse_number(y.trim_end_matches("px"))?);
+            } else {
+                return Err(format!("unknown placement `{}`", token));
+            }
+        }
+        Ok(change)
+    }
+
+    /// `placement` with the parts this change sets replaced.
+    pub fn apply(&self, placement: Placement) -> Placement {
+        Placement {
+            x: self.x.unwrap_or(placement.x),
+            scale: self.scale.unwrap_or(placement.scale),
+            y_offset: self.y_offset.unwrap_or(placement.y_offset),
+        }
+    }
+}
+
+impl Placement {
+    /// Parses a whole placement, see [`PlacementChange::parse`].
+    pub fn parse(v: &str) -> Result<Self, String> {
+        Ok(PlacementChange::parse(v)?.apply(Placement::default()))
+    }
+
+    /// The x coordinate the character is centered on. `auto` is where the character is among
+    /// the automatically placed ones and how many of them there are.
+    pub fn center_x(&self, screen_width: f32, auto: (usize, usize)) -> f32 {
+        match self.x {
+            XPosition::Auto => screen_width / (auto.1 as f32 + 1.0) * (auto.0 as f32 + 1.0),
+            XPosition::Anchor(anchor) => anchor.fraction() * screen_width,
+            XPosition::Percent(percent) => percent / 100.0 * screen_width,
+            XPosition::Pixels(pixels) => pixels,
+        }
+    }
+}
+
+fn parse_anchor(s: &str) -> Option<Anchor> {
+    Some(match s {
+        "far-left" | "farleft" => Anchor::FarLeft,
+        "left" => Anchor::Left,
+        "center" | "centre" => Anchor::Center,
+        "right" => Anchor::Right,
+        "far-right" | "farright" => Anchor::FarRight,
+        _ => return None,
+    })
+}
+
+fn parse_number(s: &str) -> Result<f32, String> {
+    s.parse()
+        .ok()
+        .filter(|n: &f32| n.is_finite())
+        .ok_or_else(|| format!("`{}` is not a number in placement", s))
+}
+
+#[test]
+fn test_placement() {
+    assert_eq!(
+        Placement::parse("Far-Left").unwrap().x,
+        XPosition::Anchor(Anchor::FarLeft)
+    );
+    assert_eq!(
+        Placement::parse("40% scale=1.5, y=-20").unwrap(),
+        Placement {
+            x: XPosition::Percent(40.0),
+            scale: 1.5,
+            y_offset: -20.0,
+        }
+    );
+    assert_eq!(
+        Placement::parse("300px").unwrap().center_x(1280.0, (0, 1)),
+        300.0
+    );
+    assert_eq!(Placement::parse("").unwrap(), Placement::default());
+    assert!(Placement::parse("behind").is_err());
+    assert!(Placement::parse("x%").is_err());
+    assert!(Placement::parse("scale=0").is_err());
+    assert!(Placement::parse("scale=-1").is_err());
+    assert!(Placement::parse("scale=nan").is_err());
+    assert!(Placement::parse("inf%").is_err());
+
+    // Without a position, characters are spaced evenly like before placements existed
+    assert_eq!(Placement::default().center_x(1200.0, (1, 2)), 800.0);
+
+    // Only what's given changes
+    let placement = Placement::parse("left y=10").unwrap();
+    let moved = PlacementChange::parse("scale=1.5")
+        .unwrap()
+        .apply(placement);
+    assert_eq!(moved.x, XPosition::Anchor(Anchor::Left));
+    assert_eq!(moved.scale, 1.5);
+    assert_eq!(moved.y_offset, 10.0);
+}
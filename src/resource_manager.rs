@@ -1,4 +1,4 @@
-use std::{cell::RefCell, collections::HashMap, sync::Arc};
+use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
 
 use ggez::{
     audio::{SoundData, Source},
@@ -7,11 +7,16 @@ use ggez::{
 };
 use log::warn;
 
-use crate::config::Config;
+use crate::{config::Config, containers::filter::Grade};
+
+// Graded images kept at once, they're made again when needed
+const MAX_GRADED_IMAGES: usize = 64;
 
 #[derive(Debug)]
 struct ResourceManagerImpl {
     image_cache: HashMap<String, Image>,
+    graded_cache: HashMap<(String, Grade), Image>,
+    rule_cache: HashMap<(String, u16, u16), Rc<Vec<f32>>>,
     sound_cache: HashMap<String, SoundData>,
     config: Arc<Config>,
 }
@@ -23,44 +28,112 @@ impl ResourceManager {
     pub fn new(config: Config) -> Self {
         ResourceManager(RefCell::new(ResourceManagerImpl {
             image_cache: HashMap::new(),
+            graded_cache: HashMap::new(),
+            rule_cache: HashMap::new(),
             sound_cache: HashMap::new(),
             config: Arc::new(config),
         }))
     }
 
     pub fn get_image(&self, ctx: &mut Context, path: &str) -> Image {
+        self.try_get_image(ctx, path).unwrap()
+    }
+
+    /// Like [`get_image`](Self::get_image), but fails if the image is missing instead of panicking.
+    pub fn try_get_image(&self, ctx: &mut Context, path: &str) -> ggez::GameResult<Image> {
         let imp = self.0.borrow();
         if let Some(o) = imp.image_cache.get(path) {
-            o.clone()
+            Ok(o.clone())
         } else {
             drop(imp);
-            let image = find_image(ctx, path);
+            let image = find_image(ctx, path)?;
             self.0
                 .borrow_mut()
                 .image_cache
                 .insert(path.to_owned(), image.clone());
-            image
+            Ok(image)
+        }
+    }
+
+    /// The image at `path` with `grade` applied to it.
+    pub fn get_graded_image(
+        &self,
+        ctx: &mut Context,
+        path: &str,
+        grade: Grade,
+    ) -> ggez::GameResult<Image> {
+        let key = (path.to_owned(), grade);
+        if let Some(o) = self.0.borrow().graded_cache.get(&key) {
+            return Ok(o.clone());
+        }
+        let image = self.try_get_image(ctx, path)?;
+        let mut pixels = image.to_rgba8(ctx)?;
+        grade.apply(&mut pixels);
+        let graded = Image::from_rgba8(ctx, image.width(), image.height(), &pixels)?;
+        let mut imp = self.0.borrow_mut();
+        // Animated backgrounds have an image for every frame
+        if imp.graded_cache.len() >= MAX_GRADED_IMAGES {
+            imp.graded_cache.clear();
+        }
+        imp.graded_cache.insert(key, graded.clone());
+        Ok(graded)
+    }
+
+    /// Forgets the graded images of grades that aren't in `grades`.
+    pub fn retain_graded(&self, grades: &[Grade]) {
+        self.0
+            .borrow_mut()
+            .graded_cache
+            .retain(|(_, grade), _| grades.contains(grade));
+    }
+
+    /// How far into a transition each pixel of the rule image `rule` in /transitions/ is
+    /// revealed, between 0 and 1, with the image stretched to `width` by `height`.
+    pub fn get_rule_mask(
+        &self,
+        ctx: &mut Context,
+        rule: &str,
+        width: u16,
+        height: u16,
+    ) -> ggez::GameResult<Rc<Vec<f32>>> {
+        let key = (rule.to_owned(), width, height);
+        if let Some(o) = self.0.borrow().rule_cache.get(&key) {
+            return Ok(o.clone());
         }
+        let mask = self.try_get_image(ctx, &format!("/transitions/{}", rule))?;
+        let mask_pixels = mask.to_rgba8(ctx)?;
+        let (width, height) = (width as usize, height as usize);
+        let (mask_width, mask_height) = (mask.width() as usize, mask.height() as usize);
+        let values = (0..width * height)
+            .map(|i| {
+                let x = (i % width) * mask_width / width;
+                let y = (i / width) * mask_height / height;
+                mask_pixels[(y * mask_width + x) * 4] as f32 / 255.0
+            })
+            .collect::<Vec<_>>();
+        let values = Rc::new(values);
+        self.0.borrow_mut().rule_cache.insert(key, values.clone());
+        Ok(values)
     }
 
-    pub fn get_sound(&self, ctx: &mut Context, path: &str) -> SoundData {
+    pub fn get_sound(&self, ctx: &mut Context, path: &str) -> ggez::GameResult<SoundData> {
         let imp = self.0.borrow();
         if let Some(o) = imp.sound_cache.get(path) {
-            o.clone()
+            Ok(o.clone())
         } else {
             drop(imp);
-            let sound = find_sound(ctx, path);
+            let sound = find_sound(ctx, path)?;
             self.0
                 .borrow_mut()
                 .sound_cache
                 .insert(path.to_owned(), sound.clone());
-            sound
+            Ok(sound)
         }
     }
 
-    pub fn get_sound_source(&self, ctx: &mut Context, path: &str) -> Source {
-        let data = self.get_sound(ctx, path);
-        Source::from_data(ctx, data).unwrap()
+    pub fn get_sound_source(&self, ctx: &mut Context, path: &str) -> ggez::GameResult<Source> {
+        let data = self.get_sound(ctx, path)?;
+        Source::from_data(ctx, data)
     }
 
     pub fn get_config(&self) -> Arc<Config> {
@@ -68,7 +141,7 @@ impl ResourceManager {
     }
 }
 
-fn find_image(ctx: &mut Context, path: &str) -> Image {
+fn find_image(ctx: &mut Context, path: &str) -> ggez::GameResult<Image> {
     let mut s: String = path.to_owned();
     if !s.starts_with('/') {
         warn!("Not prepending / in file name wastes performance");
@@ -76,30 +149,35 @@ fn find_image(ctx: &mut Context, path: &str) -> Image {
     }
     if s.contains('.') {
         if ggez::filesystem::exists(ctx, &s) {
-            return Image::new(ctx, s).unwrap();
+            return Image::new(ctx, s);
         }
     } else {
         s.push_str(".png");
         if ggez::filesystem::exists(ctx, &s) {
-            return Image::new(ctx, s).unwrap();
+            return Image::new(ctx, s);
         }
         s.truncate(s.len() - 4);
 
         s.push_str(".jpg");
         if ggez::filesystem::exists(ctx, &s) {
-            return Image::new(ctx, s).unwrap();
+            return Image::new(ctx, s);
         }
         s.truncate(s.len() - 4);
     }
-    panic!("Unable to find image {}", path);
+    Err(ggez::GameError::ResourceLoadError(format!(
+        "Unable to find image {}",
+        path
+    )))
 }
 
-fn find_sound(ctx: &mut Context, path: &str) -> SoundData {
+fn find_sound(ctx: &mut Context, path: &str) -> ggez::GameResult<SoundData> {
     let mut s: String = path.to_owned();
     s.push_str(".mp3");
     if ggez::filesystem::exists(ctx, &s) {
-        return SoundData::new(ctx, s).unwrap();
+        return SoundData::new(ctx, s);
     }
-    s.truncate(s.len() - 4);
-    panic!("Unable to find image");
+    Err(ggez::GameError::ResourceLoadError(format!(
+        "Unable to find sound {}",
+        path
+    )))
 }
@@ -0,0 +1,87 @@
+use std::collections::VecDeque;
+
+use crate::{
+    containers::{camera::CameraView, filter::FilterState},
+    save::{SavedCharacter, SavedLine, SavedSound, SavedWeather},
+};
+
+const MAX_ROLLBACK: usize = 100;
+
+#[derive(Clone)]
+pub struct RollbackEntry {
+    pub state: novelscript::NovelState,
+    pub current_background: Option<String>,
+    pub current_characters: Vec<SavedCharacter>,
+    pub sounds: Vec<SavedSound>,
+    pub nvl: Option<Vec<SavedLine>>,
+    pub camera: CameraView,
+    pub weather: Option<SavedWeather>,
+    pub filter: FilterState,
+    pub last_line: Option<String>,
+    // Length of the backlog when this line was shown
+    pub backlog_len: usize,
+}
+
+/// Snapshots of every line and choice that has been shown, the last one being the current one.
+#[derive(Default)]
+pub struct Rollback {
+    entries: VecDeque<RollbackEntry>,
+    // Index of the entry being shown if the player has rolled back, None if at the current line
+    position: Option<usize>,
+}
+
+impl Rollback {
+    /// Records a newly shown line, if the player had rolled back the lines after it are forgotten.
+    pub fn record(&mut self, entry: RollbackEntry) {
+        self.resume();
+        self.entries.push_back(entry);
+        if self.entries.len() > MAX_ROLLBACK {
+            self.entries.pop_front();
+        }
+    }
+
+    /// The entry being shown if the player has rolled back.
+    pub fn current(&self) -> Option<&RollbackEntry> {
+        self.entries.get(self.position?)
+    }
+
+    /// Continues from the entry being shown, forgetting the lines after it.
+    pub fn resume(&mut self) {
+        if let Some(position) = self.position.take() {
+            self.entries.truncate(position + 1);
+        }
+    }
+
+    pub fn back(&mut self) -> Option<&RollbackEntry> {
+        let current = self
+            .position
+            .unwrap_or_else(|| self.entries.len().saturating_sub(1));
+        if current == 0 {
+            return None;
+        }
+        self.position = Some(current - 1);
+        self.entries.get(current - 1)
+    }
+
+    pub fn forward(&mut self) -> Option<&RollbackEntry> {
+        let next = self.position? + 1;
+        self.position = if next + 1 >= self.entries.len() {
+            None
+        } else {
+            Some(next)
+        };
+        self.entries.get(next)
+    }
+
+    /// The first `n` backlog entries were forgotten.
+    pub fn trim_backlog(&mut self, n: usize) {
+        for entry in &mut self.entries {
+            entry.backlog_len = entry.backlog_len.saturating_sub(n);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.position = None;
+    }
+}
@@ -0,0 +1,578 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ggez::Context;
+use serde_json::{json, Value};
+
+use crate::{
+    containers::{camera::CameraView, filter::FilterState},
+    placement::Placement,
+    states::game::{BacklogEntry, ContinueMethod},
+    transition::Transition,
+};
+
+/// Bump this whenever the layout of [`SaveData`] changes.
+pub const SAVE_VERSION: u32 = 11;
+
+pub const SAVE_SLOT_COUNT: u32 = 8;
+
+pub const AUTOSAVE_COUNT: u32 = 3;
+
+pub const THUMBNAIL_SIZE: (u32, u32) = (256, 144);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SlotId {
+    Manual(u32),
+    Quick,
+    Auto(u32),
+}
+
+impl SlotId {
+    fn file_stem(&self) -> String {
+        match self {
+            SlotId::Manual(n) => n.to_string(),
+            SlotId::Quick => "quick".to_owned(),
+            SlotId::Auto(n) => format!("auto{}", n),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            SlotId::Manual(n) => format!("{}.", n + 1),
+            SlotId::Quick => "Quick".to_owned(),
+            SlotId::Auto(n) => format!("Auto {}", n + 1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SaveMeta {
+    pub timestamp: u64,
+    pub scene: String,
+    pub last_line: Option<String>,
+    pub playtime: f32,
+}
+
+impl SaveMeta {
+    pub fn now(scene: String, last_line: Option<String>, playtime: f32) -> Self {
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            scene,
+            last_line,
+            playtime,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SavedCharacter {
+    pub name: String,
+    pub expression: String,
+    // Images shown by each layer of a layered character
+    pub layers: BTreeMap<String, String>,
+    pub placement: Placement,
+    pub exit: Transition,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SavedSound {
+    pub name: String,
+    pub channel: String,
+}
+
+/// A line of text as written in the script.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SavedLine {
+    pub speaker: Option<String>,
+    pub content: String,
+}
+
+/// A particle effect, restarted from its seed when loaded.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SavedWeather {
+    pub name: String,
+    pub seed: u64,
+    // Drawn over the characters
+    pub front: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct SaveData {
+    pub version: u32,
+    pub meta: SaveMeta,
+    pub state: novelscript::NovelState,
+    pub continue_method: ContinueMethod,
+    pub current_background: Option<String>,
+    // In draw order
+    pub current_characters: Vec<SavedCharacter>,
+    // Looping sounds, one-shots aren't saved
+    pub sounds: Vec<SavedSound>,
+    pub backlog: Vec<BacklogEntry>,
+    // The lines on the page before the current one if in NVL mode
+    pub nvl: Option<Vec<SavedLine>>,
+    pub camera: CameraView,
+    pub weather: Option<SavedWeather>,
+    pub filter: FilterState,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    NotFound,
+    Corrupt(String),
+    Incompatible(String),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::NotFound => write!(f, "save not found"),
+            SaveError::Corrupt(e) => write!(f, "save is corrupt: {}", e),
+            SaveError::Incompatible(e) => write!(f, "save is incompatible: {}", e),
+        }
+    }
+}
+
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// `MIGRATIONS[n]` upgrades a version `n` save document to version `n + 1`.
+const MIGRATIONS: &[Migration] = &[
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+    migrate_v4_to_v5,
+    migrate_v5_to_v6,
+    migrate_v6_to_v7,
+    migrate_v7_to_v8,
+    migrate_v8_to_v9,
+    migrate_v9_to_v10,
+    migrate_v10_to_v11,
+];
+
+/// Version 0 is the original single `save.json`, it had no metadata.
+fn migrate_v0_to_v1(doc: &mut Value) -> Result<(), String> {
+    let doc = doc.as_object_mut().ok_or("save is not an object")?;
+    let scene = doc
+        .get("state")
+        .and_then(|state| state.get("scene"))
+        .cloned()
+        .unwrap_or_else(|| json!(""));
+    doc.insert(
+        "meta".to_owned(),
+        json!({
+            "timestamp": 0,
+            "scene": scene,
+            "last_line": null,
+            "playtime": 0.0,
+        }),
+    );
+    Ok(())
+}
+
+/// Version 2 stores the placement of characters, the playing music and the continue method.
+fn migrate_v1_to_v2(doc: &mut Value) -> Result<(), String> {
+    let doc = doc.as_object_mut().ok_or("save is not an object")?;
+    let characters = doc
+        .get("current_characters")
+        .and_then(|c| c.as_array())
+        .ok_or("missing current_characters")?
+        .iter()
+        .map(|c| -> Result<Value, &str> {
+            let name = c.get(0).ok_or("character without name")?;
+            let expression = c.get(1).ok_or("character without expression")?;
+            Ok(json!({
+                "name": name,
+                "expression": expression,
+                "placement": "Left",
+            }))
+        })
+        .collect::<Result<Vec<_>, &str>>()?;
+    doc.insert("current_characters".to_owned(), Value::Array(characters));
+    doc.insert("continue_method".to_owned(), json!("Normal"));
+    doc.insert("music".to_owned(), Value::Null);
+    Ok(())
+}
+
+/// Version 3 stores the dialogue backlog.
+fn migrate_v2_to_v3(doc: &mut Value) -> Result<(), String> {
+    let doc = doc.as_object_mut().ok_or("save is not an object")?;
+    doc.insert("backlog".to_owned(), json!([]));
+    Ok(())
+}
+
+/// Version 4 replaces the left/right placement with a position, scale and offset. The old
+/// placement didn't change where characters were drawn, they were spaced evenly.
+fn migrate_v3_to_v4(doc: &mut Value) -> Result<(), String> {
+    let characters = doc
+        .get_mut("current_characters")
+        .and_then(|c| c.as_array_mut())
+        .ok_or("missing current_characters")?;
+    for character in characters {
+        character["placement"] = json!({
+            "x": "Auto",
+            "scale": 1.0,
+            "y_offset": 0.0,
+        });
+    }
+    Ok(())
+}
+
+/// Version 5 stores the layers of layered characters.
+fn migrate_v4_to_v5(doc: &mut Value) -> Result<(), String> {
+    let characters = doc
+        .get_mut("current_characters")
+        .and_then(|c| c.as_array_mut())
+        .ok_or("missing current_characters")?;
+    for character in characters {
+        character["layers"] = json!({});
+    }
+    Ok(())
+}
+
+/// Version 6 stores the NVL page, saves before it were always in ADV mode.
+fn migrate_v5_to_v6(doc: &mut Value) -> Result<(), String> {
+    let doc = doc.as_object_mut().ok_or("save is not an object")?;
+    doc.insert("nvl".to_owned(), Value::Null);
+    Ok(())
+}
+
+/// Version 7 stores the camera view.
+fn migrate_v6_to_v7(doc: &mut Value) -> Result<(), String> {
+    let doc = doc.as_object_mut().ok_or("save is not an object")?;
+    doc.insert(
+        "camera".to_owned(),
+        json!({ "x": 0.5, "y": 0.5, "zoom": 1.0 }),
+    );
+    Ok(())
+}
+
+/// Version 8 stores the particle effect.
+fn migrate_v7_to_v8(doc: &mut Value) -> Result<(), String> {
+    let doc = doc.as_object_mut().ok_or("save is not an object")?;
+    doc.insert("weather".to_owned(), Value::Null);
+    Ok(())
+}
+
+/// Version 9 stores the stage filters.
+fn migrate_v8_to_v9(doc: &mut Value) -> Result<(), String> {
+    let doc = doc.as_object_mut().ok_or("save is not an object")?;
+    doc.insert(
+        "filter".to_owned(),
+        serde_json::to_value(FilterState::default()).map_err(|e| e.to_string())?,
+    );
+    Ok(())
+}
+
+/// Version 10 stores the looping sound of every channel instead of only the music.
+fn migrate_v9_to_v10(doc: &mut Value) -> Result<(), String> {
+    let doc = doc.as_object_mut().ok_or("save is not an object")?;
+    let sounds = match doc.remove("music") {
+        Some(Value::Null) | None => vec![],
+        Some(music) => vec![music],
+    };
+    doc.insert("sounds".to_owned(), Value::Array(sounds));
+    Ok(())
+}
+
+/// Version 11 stores how characters leave the stage, they used to fade out after loading.
+fn migrate_v10_to_v11(doc: &mut Value) -> Result<(), String> {
+    let characters = doc
+        .get_mut("current_characters")
+        .and_then(|c| c.as_array_mut())
+        .ok_or("missing current_characters")?;
+    for character in characters {
+        character["exit"] = json!("Fade");
+    }
+    Ok(())
+}
+
+fn document_version(doc: &Value) -> u64 {
+    match doc.get("version").and_then(|v| v.as_u64()) {
+        Some(version) => version,
+        // Saves before version 2 didn't store their version
+        None if doc.get("meta").is_some() => 1,
+        None => 0,
+    }
+}
+
+/// Upgrades a save document of any older version to [`SAVE_VERSION`].
+pub fn migrate(mut doc: Value) -> Result<Value, SaveError> {
+    let version = document_version(&doc);
+    if version > SAVE_VERSION as u64 {
+        return Err(SaveError::Incompatible(format!(
+            "made with a newer version (save version {}, supported {})",
+            version, SAVE_VERSION
+        )));
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(&mut doc).map_err(SaveError::Corrupt)?;
+    }
+    doc.as_object_mut()
+        .ok_or_else(|| SaveError::Corrupt("save is not an object".to_owned()))?
+        .insert("version".to_owned(), json!(SAVE_VERSION));
+    Ok(doc)
+}
+
+pub fn save_path(short_game_name: &str, slot: SlotId) -> PathBuf {
+    PathBuf::new()
+        .join("/")
+        .join(short_game_name)
+        .join("saves")
+        .join(format!("{}.json", slot.file_stem()))
+}
+
+/// The single save file used before save slots existed, shown as the first slot.
+fn legacy_save_path(short_game_name: &str) -> PathBuf {
+    PathBuf::new()
+        .join("/")
+        .join(short_game_name)
+        .join("save.json")
+}
+
+pub fn thumbnail_path(short_game_name: &str, slot: SlotId) -> PathBuf {
+    save_path(short_game_name, slot).with_extension("png")
+}
+
+/// Returns the scene `state` is in and its index within that scene.
+/// NovelState doesn't expose its position but the serialized form does.
+/// Returns `None` if either is missing, rather than guessing a position.
+pub fn script_position(state: &novelscript::NovelState) -> Option<(String, usize)> {
+    let value = serde_json::to_value(state).ok()?;
+    let scene = value.get("scene")?.as_str()?.to_owned();
+    let index = value.get("index")?.as_u64()? as usize;
+    Some((scene, index))
+}
+
+/// Takes a screenshot of the last frame and scales it down to thumbnail size.
+pub fn capture_thumbnail(ctx: &mut Context) -> ggez::GameResult<image::RgbaImage> {
+    let img = ggez::graphics::screenshot(ctx)?;
+    let data = img.to_rgba8(ctx)?;
+    let img = image::RgbaImage::from_raw(img.width() as u32, img.height() as u32, data)
+        .expect("Screenshot has an invalid size");
+    Ok(image::imageops::resize(
+        &img,
+        THUMBNAIL_SIZE.0,
+        THUMBNAIL_SIZE.1,
+        image::imageops::FilterType::Triangle,
+    ))
+}
+
+pub fn write_save(
+    ctx: &mut Context,
+    short_game_name: &str,
+    slot: SlotId,
+    data: &SaveData,
+    thumbnail: Option<&image::RgbaImage>,
+) -> ggez::GameResult {
+    let path = save_path(short_game_name, slot);
+    if !ggez::filesystem::exists(ctx, path.parent().unwrap()) {
+        ggez::filesystem::create_dir(ctx, path.parent().unwrap())?;
+    }
+    serde_json::to_writer(ggez::filesystem::create(ctx, &path)?, data)
+        .map_err(|e| ggez::GameError::ResourceLoadError(e.to_string()))?;
+
+    if let Some(thumbnail) = thumbnail {
+        let mut file = ggez::filesystem::create(ctx, thumbnail_path(short_game_name, slot))?;
+        image::DynamicImage::ImageRgba8(thumbnail.clone())
+            .write_to(&mut file, image::ImageOutputFormat::Png)
+            .map_err(|e| ggez::GameError::ResourceLoadError(e.to_string()))?;
+    }
+    Ok(())
+}
+
+/// The file the save in `slot` is stored in, if there is one.
+fn existing_save_path(
+    ctx: &mut Context,
+    short_game_name: &str,
+    slot: SlotId,
+) -> Result<PathBuf, SaveError> {
+    let path = save_path(short_game_name, slot);
+    if ggez::filesystem::exists(ctx, &path) {
+        return Ok(path);
+    }
+    let path = legacy_save_path(short_game_name);
+    if slot == SlotId::Manual(0) && ggez::filesystem::exists(ctx, &path) {
+        Ok(path)
+    } else {
+        Err(SaveError::NotFound)
+    }
+}
+
+/// The save document at `path` upgraded to [`SAVE_VERSION`].
+fn read_document(ctx: &mut Context, path: &Path) -> Result<Value, SaveError> {
+    let file = ggez::filesystem::open(ctx, path).map_err(|e| SaveError::Corrupt(e.to_string()))?;
+    let doc: Value = serde_json::from_reader(std::io::BufReader::new(file))
+        .map_err(|e| SaveError::Corrupt(e.to_string()))?;
+    migrate(doc)
+}
+
+pub fn read_save(
+    ctx: &mut Context,
+    short_game_name: &str,
+    slot: SlotId,
+) -> Result<SaveData, SaveError> {
+    let path = existing_save_path(ctx, short_game_name, slot)?;
+    let doc = read_document(ctx, &path)?;
+    // Anything that fails here is most likely a change in novelscript's state
+    serde_json::from_value(doc).map_err(|e| SaveError::Incompatible(e.to_string()))
+}
+
+/// Only the version and metadata of a save, the rest of the file is skipped instead of loaded.
+#[derive(serde::Deserialize)]
+struct SaveHeader {
+    version: Option<u64>,
+    meta: Option<Value>,
+}
+
+/// The metadata shown in the save window, without loading the rest of the save.
+pub fn read_meta(
+    ctx: &mut Context,
+    short_game_name: &str,
+    slot: SlotId,
+) -> Result<SaveMeta, SaveError> {
+    let path = existing_save_path(ctx, short_game_name, slot)?;
+    let file = ggez::filesystem::open(ctx, &path).map_err(|e| SaveError::Corrupt(e.to_string()))?;
+    let header: SaveHeader = serde_json::from_reader(std::io::BufReader::new(file))
+        .map_err(|e| SaveError::Corrupt(e.to_string()))?;
+    let meta = match header {
+        SaveHeader {
+            version: Some(version),
+            meta: Some(meta),
+        } if version == SAVE_VERSION as u64 => meta,
+        // Older saves have to be migrated to find their metadata
+        _ => read_document(ctx, &path)?
+            .get_mut("meta")
+            .map(Value::take)
+            .ok_or_else(|| SaveError::Corrupt("save has no metadata".to_owned()))?,
+    };
+    serde_json::from_value(meta).map_err(|e| SaveError::Corrupt(e.to_string()))
+}
+
+/// Only the time a save was written, the rest of the file is skipped instead of loaded.
+#[derive(serde::Deserialize)]
+struct SaveTimestamp {
+    meta: MetaTimestamp,
+}
+
+#[derive(serde::Deserialize)]
+struct MetaTimestamp {
+    timestamp: u64,
+}
+
+/// When the save in `slot` was written, without reading all of it.
+fn read_timestamp(ctx: &mut Context, short_game_name: &str, slot: SlotId) -> Option<u64> {
+    let path = save_path(short_game_name, slot);
+    if !ggez::filesystem::exists(ctx, &path) {
+        return None;
+    }
+    let file = ggez::filesystem::open(ctx, &path).ok()?;
+    serde_json::from_reader::<_, SaveTimestamp>(std::io::BufReader::new(file))
+        .ok()
+        .map(|save| save.meta.timestamp)
+}
+
+/// The autosave slot that was written the longest time ago.
+pub fn oldest_autosave(ctx: &mut Context, short_game_name: &str) -> u32 {
+    (0..AUTOSAVE_COUNT)
+        .min_by_key(|&n| read_timestamp(ctx, short_game_name, SlotId::Auto(n)).unwrap_or(0))
+        .unwrap_or(0)
+}
+
+pub fn read_thumbnail(
+    ctx: &mut Context,
+    short_game_name: &str,
+    slot: SlotId,
+) -> Option<ggez::graphics::Image> {
+    let path = thumbnail_path(short_game_name, slot);
+    if ggez::filesystem::exists(ctx, &path) {
+        ggez::graphics::Image::new(ctx, path).ok()
+    } else {
+        None
+    }
+}
+
+#[test]
+fn test_migrate_legacy_save() {
+    let doc = json!({
+        "state": { "scene": "start" },
+        "current_background": "Bridge",
+        "current_characters": [["Yukio", "normal"]],
+    });
+    let doc = migrate(doc).unwrap();
+    assert_eq!(doc["version"], json!(SAVE_VERSION));
+    assert_eq!(doc["meta"]["scene"], json!("start"));
+    assert_eq!(doc["continue_method"], json!("Normal"));
+    assert_eq!(doc["backlog"], json!([]));
+    assert_eq!(doc["nvl"], Value::Null);
+    assert_eq!(doc["weather"], Value::Null);
+    assert_eq!(doc["sounds"], json!([]));
+    assert_eq!(
+        serde_json::from_value::<FilterState>(doc["filter"].clone()).unwrap(),
+        FilterState::default()
+    );
+    assert_eq!(
+        serde_json::from_value::<CameraView>(doc["camera"].clone()).unwrap(),
+        CameraView::default()
+    );
+    assert_eq!(
+        doc["current_characters"],
+        json!([{
+            "name": "Yukio",
+            "expression": "normal",
+            "layers": {},
+            "placement": { "x": "Auto", "scale": 1.0, "y_offset": 0.0 },
+            "exit": "Fade",
+        }])
+    );
+
+    let doc = migrate(json!({
+        "version": 9,
+        "music": { "name": "bgm", "channel": "music" },
+        "current_characters": [],
+    }))
+    .unwrap();
+    assert_eq!(
+        doc["sounds"],
+        json!([{ "name": "bgm", "channel": "music" }])
+    );
+    assert!(doc.get("music").is_none());
+
+    assert!(matches!(
+        migrate(json!({ "version": SAVE_VERSION + 1 })),
+        Err(SaveError::Incompatible(..))
+    ));
+}
+
+#[test]
+fn test_save_timestamp() {
+    let save = json!({
+        "version": SAVE_VERSION,
+        "meta": { "timestamp": 1234, "scene": "start", "last_line": null, "playtime": 5.0 },
+        "backlog": [{ "Choice": "Yes" }],
+    });
+    let timestamp = serde_json::from_value::<SaveTimestamp>(save).unwrap();
+    assert_eq!(timestamp.meta.timestamp, 1234);
+}
+
+#[test]
+fn test_script_position() {
+    let mut novel = novelscript::Novel::new();
+    novel.add_scene("test".into(), "_: One\n\n_: Two\n");
+    let mut state = novel.new_state("test");
+
+    novel.next(&mut state).expect("first line");
+    let first = script_position(&state).expect("position of first line");
+    novel.next(&mut state).expect("second line");
+    let second = script_position(&state).expect("position of second line");
+
+    assert_eq!(first.0, "test");
+    assert_eq!(second.0, "test");
+    assert_ne!(first, second);
+}
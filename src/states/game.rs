@@ -1,12 +1,47 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+};
 
+use crate::audio::Track;
+use crate::command::Command;
+use crate::config::{SkipMode, UserConfig};
 use crate::containers::{
-    background::BackgroundContainer, button::Button, character::CharacterContainer,
-    gamescreen::Action, gamescreen::GameScreen, stackcontainer::Direction,
-    stackcontainer::StackContainer, ui::MenuButtonId, ui::UI, Update,
+    background::BackgroundContainer,
+    backlog_window::BacklogWindow,
+    button::Button,
+    camera::{Camera, CameraView},
+    character::CharacterContainer,
+    filter::{FilterState, StageFilter},
+    gamescreen::Action,
+    gamescreen::{GameScreen, Notice, Window},
+    glossary_window::GlossaryWindow,
+    nvl::NvlPage,
+    particles::ParticleLayer,
+    rich_text::{self, Format},
+    save_window::{SaveSlot, SaveWindow, SaveWindowMode},
+    sprite::Sprite,
+    stackcontainer::Direction,
+    stackcontainer::StackContainer,
+    ui::MenuButtonId,
+    ui::UI,
+    Update,
+};
+use crate::draw::load_text;
+use crate::node::{load_background_tween, load_character};
+use crate::placement::Placement;
+use crate::rollback::{Rollback, RollbackEntry};
+use crate::save::{
+    SaveData, SaveError, SaveMeta, SavedCharacter, SavedLine, SavedSound, SavedWeather, SlotId,
+    AUTOSAVE_COUNT, SAVE_SLOT_COUNT, SAVE_VERSION,
+};
+use crate::transition::{BackgroundTransition, Transition};
+use crate::tween::NonTweener;
+use crate::{
+    helpers::{format_playtime, format_timestamp, points_to_rect, Position},
+    resource_manager::ResourceManager,
 };
-use crate::node::{load_background_tween, load_character_tween};
-use crate::{helpers::Position, resource_manager::ResourceManager};
 use ggez::{
     self,
     event::{KeyCode, KeyMods, MouseButton},
@@ -14,66 +49,189 @@ use ggez::{
 };
 use ggez::{audio::SoundSource, graphics::Drawable};
 use ggez::{
-    graphics::{self, DrawParam},
+    graphics::{self, DrawMode, DrawParam, FillOptions, Rect, Text, TextFragment},
     GameResult,
 };
+use log::{info, warn};
 
 use super::StateEventHandler;
 
-#[derive(Copy, Clone)]
-pub enum Placement {
-    Left,
-    Right,
-}
-
-impl Placement {
-    pub fn parse(v: &str) -> Self {
-        match v {
-            "left" => Placement::Left,
-            "right" => Placement::Right,
-            _ => Placement::Left,
-        }
-    }
+#[derive(Clone)]
+pub struct CharacterLayer {
+    pub path: String,
+    pub image: graphics::Image,
+    // Offset from the top left of the first layer, in image pixels
+    pub offset: glam::Vec2,
 }
 
 pub struct Character {
     pub name: String,
+    // Folder in /char the images are from
+    pub sprite: String,
     pub expression: String,
-    pub image: graphics::Image,
+    // Drawn bottom to top, a single layer unless the character is layered in characters.ini
+    pub layers: Vec<CharacterLayer>,
+    // Image chosen for each layer of a layered character
+    pub layer_values: BTreeMap<String, String>,
+    // The expression being crossfaded from
+    pub previous_layers: Option<Vec<CharacterLayer>>,
+    // Progress of the crossfade, 1 when only the current image is shown
+    pub swap: f32,
     pub position: Placement,
     pub alpha: f32,
+    // Moves the character away from its placement during transitions
+    pub offset: glam::Vec2,
+    // Used when the character is removed without a transition
+    pub exit: Transition,
+    // 1 when darkened for not being the speaker
+    pub dim: f32,
+}
+
+impl Character {
+    /// The size the character is drawn at, the size of its first layer.
+    pub fn size(&self) -> glam::Vec2 {
+        self.layers
+            .first()
+            .map_or(glam::Vec2::zero(), |layer| self.image_size(&layer.image))
+    }
+
+    pub fn image_size(&self, image: &graphics::Image) -> glam::Vec2 {
+        let height = crate::helpers::target_size().y * (4.0 / 5.0) * self.position.scale;
+        glam::Vec2::new(
+            height * (image.width() as f32 / image.height() as f32),
+            height,
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct BackgroundLayer {
+    // Each frame is part of an image, the whole image unless it's from a sprite sheet
+    pub frames: Vec<(String, graphics::Image, Rect)>,
+    pub fps: f32,
+    pub parallax: f32,
+    pub scroll: f32,
+}
+
+impl BackgroundLayer {
+    pub fn frame(&self, time: f32) -> &(String, graphics::Image, Rect) {
+        let n = (time * self.fps) as usize % self.frames.len();
+        &self.frames[n]
+    }
+
+    /// Size of a frame in pixels.
+    pub fn size(&self) -> glam::Vec2 {
+        let (_, image, src) = &self.frames[0];
+        glam::Vec2::new(image.width() as f32 * src.w, image.height() as f32 * src.h)
+    }
 }
 
 #[derive(Debug)]
 pub struct Background {
     pub name: String,
-    pub image: graphics::Image,
+    // Drawn bottom to top
+    pub layers: Vec<BackgroundLayer>,
+    // Seconds since the background was shown, used for animations
+    pub time: f32,
     pub fade: f32,
+    // Moves the background away from the screen during slides and pushes, in pixels
+    pub offset: glam::Vec2,
+    // Part of the image shown during wipes, as fractions of the image
+    pub clip: Rect,
+    // 0 when faded to black
+    pub brightness: f32,
+    // The steps of a rule image transition, shown instead of the image according to `fade`
+    pub reveal: Option<Rc<Vec<graphics::Image>>>,
 }
 
 impl Background {
-    pub fn new(image: graphics::Image, name: String) -> Self {
+    pub fn new(layers: Vec<BackgroundLayer>, name: String) -> Self {
         Self {
-            image,
+            layers,
             name,
+            time: 0.0,
             fade: 0.0,
+            offset: glam::Vec2::zero(),
+            clip: Rect::one(),
+            brightness: 1.0,
+            reveal: None,
+        }
+    }
+
+    /// The path and image if the background is a single image without animation.
+    pub fn single_image(&self) -> Option<(&str, &graphics::Image)> {
+        match self.layers.as_slice() {
+            [layer] if layer.frames.len() == 1 && layer.scroll == 0.0 => {
+                Some((layer.frames[0].0.as_str(), &layer.frames[0].1))
+            }
+            _ => None,
         }
     }
 }
 
-#[derive(PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum ContinueMethod {
     Auto(f32),
     Skip(f32),
     Normal,
 }
 
+// Oldest backlog entries are forgotten past this many
+const MAX_BACKLOG: usize = 500;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum BacklogEntry {
+    Line {
+        speaker: Option<String>,
+        content: String,
+    },
+    Choice(String),
+}
+
+#[derive(PartialEq)]
+pub enum AutosaveState {
+    None,
+    // Waiting for the frame to be drawn so it can be used as the thumbnail
+    Pending,
+    Drawn,
+}
+
+// A voice blip is played every this many characters
+const BLIP_INTERVAL: usize = 3;
+
 pub struct Audio {
-    pub sfx: Option<ggez::audio::Source>,
-    pub music: Option<ggez::audio::Source>,
+    // The sound playing on each channel
+    pub channels: HashMap<String, Track>,
+    // Sounds that were stopped or replaced and are still fading out
+    pub fading: Vec<Track>,
     pub ui_sfx: Rc<RefCell<Option<ggez::audio::Source>>>,
 }
 
+impl Audio {
+    /// Name of the sound playing on `channel`.
+    pub fn playing(&self, channel: &str) -> Option<&String> {
+        self.channels.get(channel).map(|track| &track.name)
+    }
+
+    /// Fades out the sound playing on `channel` over `duration` seconds.
+    pub fn stop(&mut self, channel: &str, duration: f32) {
+        if let Some(mut track) = self.channels.remove(channel) {
+            track.stop(duration);
+            self.fading.push(track);
+        }
+    }
+
+    /// Updates the volumes and drops the sounds that have finished.
+    pub fn update(&mut self, dt: f32, user: &UserConfig) {
+        for track in self.channels.values_mut().chain(self.fading.iter_mut()) {
+            let volume = user.master_volume * user.channel_volumes.volume(&track.channel);
+            track.update(dt, volume);
+        }
+        self.channels.retain(|_, track| !track.is_done());
+        self.fading.retain(|track| !track.is_done());
+    }
+}
+
 pub struct GameState {
     pub novel: novelscript::Novel,
     pub state: novelscript::NovelState,
@@ -82,6 +240,17 @@ pub struct GameState {
     pub screen: GameScreen,
     pub audio: Audio,
     pub is_end: bool,
+    pub playtime: f32,
+    pub last_line: Option<String>,
+    pub rollback: Rollback,
+    pub backlog: Vec<BacklogEntry>,
+    // Whether the line being shown had been read before, in any playthrough
+    pub line_is_read: bool,
+    // Whether the line being shown was restored from a save or by rolling back, no-wait
+    // lines don't continue on their own then
+    pub restored: bool,
+    pub autosave: AutosaveState,
+    pub next_autosave: u32,
 }
 
 impl GameState {
@@ -96,14 +265,18 @@ impl GameState {
             resources,
             continue_method: ContinueMethod::Normal,
             audio: Audio {
-                sfx: None,
-                music: None,
+                channels: HashMap::new(),
+                fading: Vec::new(),
                 ui_sfx: Rc::new(RefCell::new(None)),
             },
             screen: GameScreen {
                 current_background: None,
                 current_characters: CharacterContainer::new(),
+                camera: Camera::new(CameraView::default()),
+                weather: None,
+                filter: StageFilter::new(ctx, resources, FilterState::default()).unwrap(),
                 action: Action::None,
+                nvl: None,
                 ui: UI {
                     menu: StackContainer::new(
                         Position::BottomLeft.add_in(ctx, glam::Vec2::new(10.0, 40.0)),
@@ -112,15 +285,29 @@ impl GameState {
                         Direction::Horizontal,
                     ),
                 },
+                window: Window::None,
+                notice: None,
                 is_screenshot: false,
             },
             is_end: false,
+            playtime: 0.0,
+            last_line: None,
+            rollback: Rollback::default(),
+            backlog: Vec::new(),
+            line_is_read: false,
+            restored: false,
+            autosave: AutosaveState::None,
+            next_autosave: crate::save::oldest_autosave(
+                ctx,
+                &resources.get_config().short_game_name,
+            ),
         };
         for (n, d) in [
             ("Save", MenuButtonId::Save),
             ("Load", MenuButtonId::Load),
             ("Auto", MenuButtonId::Auto),
             ("Skip", MenuButtonId::Skip),
+            ("Log", MenuButtonId::Backlog),
         ]
         .iter()
         .enumerate()
@@ -142,13 +329,6 @@ impl GameState {
     }
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
-pub struct SaveData {
-    pub state: novelscript::NovelState,
-    pub current_background: Option<String>,
-    pub current_characters: Vec<(String, String)>,
-}
-
 pub fn consume_node(
     ctx: &mut Context,
     node: &novelscript::SceneNodeUser,
@@ -161,14 +341,7 @@ pub fn consume_node(
             crate::node::load_data_node(ctx, screen, node, resources, audio.ui_sfx.clone())?;
         }
         novelscript::SceneNodeUser::Load(node) => {
-            crate::node::load_load_node(
-                ctx,
-                resources,
-                screen,
-                node.clone(),
-                &mut audio.sfx,
-                &mut audio.music,
-            )?;
+            crate::node::load_load_node(ctx, resources, screen, node.clone(), audio)?;
         }
     };
     Ok(())
@@ -176,15 +349,94 @@ pub fn consume_node(
 
 impl GameState {
     fn continue_text(&mut self, ctx: &mut Context, inc: bool) -> ggez::GameResult {
+        if inc {
+            self.restored = false;
+        }
         let node = if inc {
             self.novel.next(&mut self.state)
         } else {
             self.novel.current(&mut self.state)
         };
         if let Some(node) = node {
+            if let Some(command) = Command::from_node(node) {
+                let command = command.map_err(|e| {
+                    ggez::GameError::ResourceLoadError(format!("Invalid command: {}", e))
+                })?;
+                crate::node::run_command(
+                    ctx,
+                    &mut self.screen,
+                    self.resources,
+                    &mut self.audio,
+                    command,
+                )?;
+                return self.continue_text(ctx, true);
+            }
+            if let novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+                speaker,
+                content,
+            }) = node
+            {
+                let plain = crate::markup::Markup::parse(content).plain();
+                self.last_line = Some(match speaker {
+                    Some(speaker) => format!(
+                        "{}: {}",
+                        self.resources.get_config().display_name(speaker),
+                        plain
+                    ),
+                    None => plain.clone(),
+                });
+                self.line_is_read = if inc {
+                    let key = match crate::save::script_position(&self.state) {
+                        Some((scene, index)) => format!("{}:{}", scene, index),
+                        None => content.clone(),
+                    };
+                    let config = self.resources.get_config();
+                    let mut read = config.read.borrow_mut();
+                    read.mark_read(key)
+                } else {
+                    true
+                };
+                if inc {
+                    let entry = BacklogEntry::Line {
+                        speaker: speaker.clone(),
+                        content: plain,
+                    };
+                    self.push_backlog(entry);
+                }
+            }
+            // Choices are recorded too so rolling back from one goes to the line before it
+            let is_rollback_point = matches!(
+                node,
+                novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text { .. })
+                    | novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Choice(..))
+            );
+            let is_autosave_point = matches!(
+                node,
+                novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Choice(..))
+                    | novelscript::SceneNodeUser::Load(
+                        novelscript::SceneNodeLoad::Background { .. }
+                    )
+            );
+            if inc && is_autosave_point {
+                self.autosave = AutosaveState::Pending;
+            }
             consume_node(ctx, node, &mut self.screen, self.resources, &mut self.audio)?;
             if let novelscript::SceneNodeUser::Load(..) = node {
-                self.continue_text(ctx, true).unwrap();
+                self.continue_text(ctx, true)?;
+            } else if inc && is_rollback_point {
+                let entry = RollbackEntry {
+                    state: self.state.clone(),
+                    current_background: self.saved_background(),
+                    current_characters: self.saved_characters(),
+                    sounds: self.saved_sounds(),
+                    nvl: self.saved_nvl(),
+                    camera: self.screen.camera.target,
+                    weather: self.saved_weather(),
+                    filter: self.screen.filter.target.clone(),
+                    last_line: self.last_line.clone(),
+                    backlog_len: self.backlog.len(),
+                };
+                self.rollback.record(entry);
             }
         } else {
             self.is_end = true;
@@ -192,77 +444,686 @@ impl GameState {
         Ok(())
     }
 
-    pub fn on_save_click(&mut self, ctx: &mut Context) {
-        println!("Saving game");
-        serde_json::to_writer(
-            ggez::filesystem::create(
+    fn push_backlog(&mut self, entry: BacklogEntry) {
+        // Lines after the point the player rolled back to didn't happen anymore
+        if let Some(current) = self.rollback.current() {
+            self.backlog.truncate(current.backlog_len);
+        }
+        self.rollback.resume();
+        self.backlog.push(entry);
+        if self.backlog.len() > MAX_BACKLOG {
+            let excess = self.backlog.len() - MAX_BACKLOG;
+            self.backlog.drain(..excess);
+            self.rollback.trim_backlog(excess);
+        }
+    }
+
+    fn choose(&mut self, ctx: &mut Context, n: u32) {
+        let choice = if let Action::Choice(choices) = &self.screen.action {
+            choices
+                .children
+                .get(n as usize)
+                .map(|(button, _)| button.text.contents())
+        } else {
+            None
+        };
+        if let Some(choice) = choice {
+            self.push_backlog(BacklogEntry::Choice(choice));
+        }
+        self.state.set_choice(n as i32 + 1);
+        self.continue_text(ctx, true).unwrap();
+    }
+
+    pub fn open_backlog_window(&mut self, ctx: &mut Context) {
+        let config = self.resources.get_config();
+        let bounds = points_to_rect(
+            Position::TopLeft.add_in(ctx, glam::Vec2::new(100.0, 80.0)),
+            Position::BottomRight.add_in(ctx, glam::Vec2::new(100.0, 40.0)),
+        );
+        let entries = self
+            .backlog
+            .iter()
+            .map(|entry| {
+                let mut text = Text::default();
+                match entry {
+                    BacklogEntry::Line { speaker, content } => {
+                        if let Some(speaker) = speaker {
+                            text.add(
+                                TextFragment::new(format!("{}: ", config.display_name(speaker)))
+                                    .color(
+                                        config
+                                            .characters
+                                            .get(speaker)
+                                            .map_or(graphics::WHITE, |c| c.color),
+                                    ),
+                            );
+                        }
+                        text.add(content.as_str());
+                    }
+                    BacklogEntry::Choice(choice) => {
+                        text.add(
+                            TextFragment::new(format!("> {}", choice))
+                                .color(config.ui.button_highlight_color),
+                        );
+                    }
+                }
+                text.set_bounds([bounds.w, f32::INFINITY], graphics::Align::Left);
+                text
+            })
+            .collect();
+        self.screen.window = Window::Backlog(BacklogWindow {
+            panel: graphics::Mesh::new_rectangle(
+                ctx,
+                DrawMode::Fill(FillOptions::DEFAULT),
+                Rect {
+                    x: 0.0,
+                    y: 0.0,
+                    w: crate::helpers::target_size().x,
+                    h: crate::helpers::target_size().y,
+                },
+                graphics::Color {
+                    r: 0.0,
+                    g: 0.0,
+                    b: 0.0,
+                    a: 0.9,
+                },
+            )
+            .unwrap(),
+            exit_button: Button::new(
                 ctx,
-                format!("/{}/save.json", self.resources.get_config().short_game_name),
+                self.resources,
+                points_to_rect(
+                    Position::TopRight.add_in(ctx, glam::Vec2::new(55.0, 5.0)),
+                    Position::TopRight.add_in(ctx, glam::Vec2::new(5.0, 55.0)),
+                ),
+                "X".into(),
+                self.audio.ui_sfx.clone(),
+            )
+            .unwrap(),
+            entries,
+            bounds,
+            scroll: 0,
+        });
+    }
+
+    fn open_glossary_window(&mut self, ctx: &mut Context, key: &str) {
+        let entry = match self.resources.get_config().glossary.get(key) {
+            Some(entry) => entry,
+            None => {
+                warn!("Glossary entry '{}' not found", key);
+                return;
+            }
+        };
+        let bounds = points_to_rect(
+            Position::Center.add_in(ctx, glam::Vec2::new(-320.0, -130.0)),
+            Position::Center.add_in(ctx, glam::Vec2::new(320.0, 130.0)),
+        );
+        let mut definition = Text::new(entry.definition.as_str());
+        definition.set_bounds([bounds.w - 40.0, bounds.h - 70.0], graphics::Align::Left);
+        self.screen.window = Window::Glossary(GlossaryWindow {
+            panel: graphics::Mesh::new_rectangle(
+                ctx,
+                DrawMode::Fill(FillOptions::DEFAULT),
+                bounds,
+                graphics::Color {
+                    r: 0.0,
+                    g: 0.0,
+                    b: 0.0,
+                    a: 0.9,
+                },
             )
             .unwrap(),
-            &SaveData {
-                state: self.state.clone(), // Must clone to be able to be serialized
-                current_characters: self
-                    .screen
-                    .current_characters
-                    .current
+            name: Sprite {
+                content: Text::new(
+                    TextFragment::new(entry.name.as_str())
+                        .color(rich_text::LINK_COLOR)
+                        .scale(graphics::PxScale::from(28.0)),
+                ),
+                param: DrawParam::new()
+                    .dest(Position::TopLeft.add_in_from(&bounds, glam::Vec2::new(20.0, 15.0))),
+            },
+            definition: Sprite {
+                content: definition,
+                param: DrawParam::new()
+                    .dest(Position::TopLeft.add_in_from(&bounds, glam::Vec2::new(20.0, 55.0))),
+            },
+        });
+    }
+
+    fn saved_characters(&self) -> Vec<SavedCharacter> {
+        self.screen
+            .current_characters
+            .current
+            .iter()
+            .map(|n| {
+                let cur = n.get_current();
+                // Must clone to be able to be serialized
+                SavedCharacter {
+                    name: cur.name.clone(),
+                    expression: cur.expression.clone(),
+                    layers: cur.layer_values.clone(),
+                    placement: cur.position,
+                    exit: cur.exit,
+                }
+            })
+            .collect()
+    }
+
+    fn saved_background(&self) -> Option<String> {
+        self.screen
+            .current_background
+            .as_ref()
+            .map(|n| n.current.get_current().1.name.clone()) // Must clone to be able to be serialized
+    }
+
+    fn saved_sounds(&self) -> Vec<SavedSound> {
+        let mut sounds = self
+            .audio
+            .channels
+            .values()
+            .filter(|track| track.looping)
+            .map(|track| SavedSound {
+                name: track.name.clone(),
+                channel: track.channel.clone(),
+            })
+            .collect::<Vec<_>>();
+        sounds.sort_by(|a, b| a.channel.cmp(&b.channel));
+        sounds
+    }
+
+    fn saved_nvl(&self) -> Option<Vec<SavedLine>> {
+        self.screen.nvl.as_ref().map(|nvl| nvl.lines.clone())
+    }
+
+    fn saved_weather(&self) -> Option<SavedWeather> {
+        self.screen.weather.as_ref().map(ParticleLayer::saved)
+    }
+
+    fn save_data(&self) -> SaveData {
+        SaveData {
+            version: SAVE_VERSION,
+            meta: SaveMeta::now(
+                crate::save::script_position(&self.state)
+                    .map(|(scene, _)| scene)
+                    .unwrap_or_default(),
+                self.last_line.clone(),
+                self.playtime,
+            ),
+            state: self.state.clone(), // Must clone to be able to be serialized
+            continue_method: self.continue_method,
+            current_characters: self.saved_characters(),
+            current_background: self.saved_background(),
+            sounds: self.saved_sounds(),
+            backlog: self.backlog.clone(),
+            nvl: self.saved_nvl(),
+            camera: self.screen.camera.target,
+            weather: self.saved_weather(),
+            filter: self.screen.filter.target.clone(),
+        }
+    }
+
+    /// Replaces everything on stage without any transitions.
+    /// Fails without changing the stage if something it shows can't be loaded.
+    fn restore_stage(
+        &mut self,
+        ctx: &mut Context,
+        background: Option<String>,
+        characters: Vec<SavedCharacter>,
+        sounds: Vec<SavedSound>,
+        nvl: Option<Vec<SavedLine>>,
+        camera: CameraView,
+    ) -> Result<(), SaveError> {
+        let incompatible = |e: ggez::GameError| SaveError::Incompatible(e.to_string());
+        let characters = characters
+            .into_iter()
+            .map(|saved| -> ggez::GameResult<Character> {
+                let mut character = load_character(
+                    ctx,
+                    self.resources,
+                    saved.name,
+                    saved.expression,
+                    saved.layers,
+                    saved.placement,
+                )?;
+                character.exit = saved.exit;
+                Ok(character)
+            })
+            .collect::<ggez::GameResult<Vec<_>>>()
+            .map_err(incompatible)?;
+        let background = match background {
+            Some(name) => Some(
+                load_background_tween(
+                    ctx,
+                    self.resources,
+                    None,
+                    name,
+                    BackgroundTransition::Instant,
+                    0.0,
+                    None,
+                )
+                .map_err(incompatible)?,
+            ),
+            None => None,
+        };
+        let nvl_page = match nvl {
+            Some(_) => Some(NvlPage::new(ctx).map_err(incompatible)?),
+            None => None,
+        };
+
+        self.screen.current_characters = CharacterContainer::new();
+        self.screen.camera = Camera::new(camera);
+        for character in characters {
+            self.screen
+                .current_characters
+                .current
+                .push(Box::new(NonTweener::new(character)));
+        }
+        self.screen.current_background = background.map(|background| BackgroundContainer {
+            current: Box::new(background),
+        });
+
+        self.screen.action = Action::None;
+        self.screen.nvl = nvl_page;
+        for line in nvl.into_iter().flatten() {
+            load_text(
+                ctx,
+                &mut self.screen,
+                self.resources,
+                &line.speaker,
+                &line.content,
+            )
+            .map_err(incompatible)?;
+        }
+        self.screen.keep_nvl_line();
+
+        // Looping sounds that aren't saved are stopped, one-shots finish on their own
+        let stopped = self
+            .audio
+            .channels
+            .values()
+            .filter(|track| track.looping)
+            .filter(|track| {
+                !sounds
                     .iter()
-                    .map(|n| {
-                        let cur = n.get_current();
-                        (cur.name.clone(), cur.expression.clone()) // Must clone to be able to be serialized
-                    })
-                    .collect(),
-                current_background: self
-                    .screen
-                    .current_background
-                    .as_ref()
-                    .map(|n| n.current.get_current().1.name.clone()), // Must clone to be able to be serialized
+                    .any(|sound| sound.channel == track.channel && sound.name == track.name)
+            })
+            .map(|track| track.channel.clone())
+            .collect::<Vec<_>>();
+        for channel in stopped {
+            self.audio.stop(&channel, 0.0);
+        }
+        for sound in sounds {
+            // Don't restart sounds that are already playing
+            if self.audio.playing(&sound.channel) != Some(&sound.name) {
+                crate::node::play_sound(
+                    ctx,
+                    self.resources,
+                    &mut self.audio,
+                    sound.name,
+                    &sound.channel,
+                    Some(true),
+                    Some(0.0),
+                )
+                .map_err(|e| warn!("Unable to restore sound: {}", e))
+                .ok();
+            }
+        }
+        Ok(())
+    }
+
+    /// Restarts the particle effect from its seed.
+    fn restore_weather(&mut self, ctx: &mut Context, weather: Option<SavedWeather>) {
+        self.screen.weather = weather.and_then(|weather| {
+            ParticleLayer::new(ctx, self.resources, weather)
+                .map_err(|e| warn!("Unable to restore weather: {}", e))
+                .ok()
+        });
+    }
+
+    fn restore_rollback(&mut self, ctx: &mut Context, entry: RollbackEntry) {
+        if let Err(e) = self.restore_stage(
+            ctx,
+            entry.current_background,
+            entry.current_characters,
+            entry.sounds,
+            entry.nvl,
+            entry.camera,
+        ) {
+            warn!("Unable to roll back: {}", e);
+            return;
+        }
+        self.state = entry.state;
+        self.last_line = entry.last_line;
+        self.restore_weather(ctx, entry.weather);
+        self.screen.filter.reset(entry.filter);
+        if let Err(e) = self.continue_text(ctx, false) {
+            warn!("Unable to roll back: {}", e);
+        }
+        self.restored = true;
+        if let Action::Text(text) = &mut self.screen.action {
+            text.content.content.finish();
+        }
+    }
+
+    /// Returns false if there was nothing to roll back to.
+    pub fn roll_back(&mut self, ctx: &mut Context) -> bool {
+        if let Some(entry) = self.rollback.back().cloned() {
+            self.continue_method = ContinueMethod::Normal;
+            self.restore_rollback(ctx, entry);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn roll_forward(&mut self, ctx: &mut Context) {
+        if let Some(entry) = self.rollback.forward().cloned() {
+            self.restore_rollback(ctx, entry);
+        }
+    }
+
+    pub fn open_save_window(
+        &mut self,
+        ctx: &mut Context,
+        mode: SaveWindowMode,
+        thumbnail: Option<image::RgbaImage>,
+    ) {
+        let short_game_name = self.resources.get_config().short_game_name.clone();
+        let mut slots = (0..SAVE_SLOT_COUNT).map(SlotId::Manual).collect::<Vec<_>>();
+        // Quick and auto saves can only be loaded
+        if mode == SaveWindowMode::Load {
+            slots.push(SlotId::Quick);
+            slots.extend((0..AUTOSAVE_COUNT).map(SlotId::Auto));
+        }
+        let cell_size = (280.0, 190.0);
+        let thumbnail_size = (192.0, 108.0);
+        let columns = 4;
+        let rows = (slots.len() as f32 / columns as f32).ceil();
+        let grid_size = glam::Vec2::new(
+            cell_size.0 * columns as f32 + 10.0 * (columns as f32 - 1.0),
+            cell_size.1 * rows + 10.0 * (rows - 1.0),
+        );
+        // The slots are centered in the space below the title
+        let screen_size = crate::helpers::target_size();
+        let grid_top = 100.0;
+        let mut window = SaveWindow {
+            mode,
+            panel: graphics::Mesh::new_rectangle(
+                ctx,
+                DrawMode::Fill(FillOptions::DEFAULT),
+                Rect {
+                    x: 0.0,
+                    y: 0.0,
+                    w: crate::helpers::target_size().x,
+                    h: crate::helpers::target_size().y,
+                },
+                graphics::Color {
+                    r: 0.0,
+                    g: 0.0,
+                    b: 0.0,
+                    a: 0.9,
+                },
+            )
+            .unwrap(),
+            title: Sprite {
+                content: Text::new(match mode {
+                    SaveWindowMode::Save => "Save",
+                    SaveWindowMode::Load => "Load",
+                }),
+                param: DrawParam::new()
+                    .dest(Position::TopLeft.add_in(ctx, glam::Vec2::new(50.0, 50.0))),
             },
-        )
-        .unwrap();
-        println!("Saved game!");
+            exit_button: Button::new(
+                ctx,
+                self.resources,
+                points_to_rect(
+                    Position::TopRight.add_in(ctx, glam::Vec2::new(55.0, 5.0)),
+                    Position::TopRight.add_in(ctx, glam::Vec2::new(5.0, 55.0)),
+                ),
+                "X".into(),
+                self.audio.ui_sfx.clone(),
+            )
+            .unwrap(),
+            slots: StackContainer::new(
+                glam::Vec2::new(
+                    (screen_size.x - grid_size.x) / 2.0,
+                    grid_top + (screen_size.y - grid_top - grid_size.y).max(0.0) / 2.0,
+                ),
+                10.0,
+                cell_size,
+                Direction::Grid { columns },
+            ),
+            thumbnail,
+        };
+        for (n, slot) in slots.into_iter().enumerate() {
+            let rect = window.slots.get_rect_for(n as f32);
+            let save = crate::save::read_meta(ctx, &short_game_name, slot);
+            let thumbnail = save
+                .as_ref()
+                .ok()
+                .and_then(|_| crate::save::read_thumbnail(ctx, &short_game_name, slot))
+                .map(|image| Sprite {
+                    param: DrawParam::new()
+                        .dest([rect.x + (rect.w - thumbnail_size.0) / 2.0, rect.y + 10.0])
+                        .scale([
+                            thumbnail_size.0 / image.width() as f32,
+                            thumbnail_size.1 / image.height() as f32,
+                        ]),
+                    content: image,
+                });
+            let mut info = Text::new(match &save {
+                Ok(meta) => format!(
+                    "{} {}  ({})\n{}\n{}",
+                    slot.label(),
+                    format_timestamp(meta.timestamp),
+                    format_playtime(meta.playtime),
+                    meta.scene,
+                    meta.last_line.as_deref().unwrap_or_default(),
+                ),
+                Err(SaveError::NotFound) => slot.label(),
+                Err(e @ SaveError::Corrupt(_)) => {
+                    warn!("Save slot {:?}: {}", slot, e);
+                    format!("{} This save is corrupt", slot.label())
+                }
+                Err(e) => {
+                    warn!("Save slot {:?}: {}", slot, e);
+                    format!(
+                        "{} This save is incompatible with this version",
+                        slot.label()
+                    )
+                }
+            });
+            info.set_bounds(
+                [rect.w - 20.0, rect.h - thumbnail_size.1 - 20.0],
+                graphics::Align::Left,
+            );
+            window.slots.children.push((
+                SaveSlot {
+                    button: Button::new(
+                        ctx,
+                        self.resources,
+                        rect,
+                        if let Err(SaveError::NotFound) = save {
+                            "Empty"
+                        } else {
+                            ""
+                        }
+                        .into(),
+                        self.audio.ui_sfx.clone(),
+                    )
+                    .unwrap(),
+                    thumbnail,
+                    info: Sprite {
+                        content: info,
+                        param: DrawParam::new()
+                            .dest([rect.x + 10.0, rect.y + thumbnail_size.1 + 15.0]),
+                    },
+                    is_loadable: save.is_ok(),
+                },
+                slot,
+            ));
+        }
+        self.screen.window = Window::Save(window);
+    }
+
+    pub fn on_save_click(&mut self, ctx: &mut Context) {
+        let thumbnail = crate::save::capture_thumbnail(ctx)
+            .map_err(|e| warn!("Unable to capture save thumbnail: {}", e))
+            .ok();
+        self.open_save_window(ctx, SaveWindowMode::Save, thumbnail);
     }
 
     pub fn on_load_click(&mut self, ctx: &mut Context) {
-        println!("Loading game!");
-        let path = format!("/{}/save.json", self.resources.get_config().short_game_name);
-        if ggez::filesystem::exists(ctx, &path) {
-            let file = ggez::filesystem::open(ctx, &path).unwrap();
-            let savedata: SaveData = serde_json::from_reader(file).unwrap();
-            self.state = savedata.state;
-            self.screen.current_characters.current = Vec::new();
-            for (name, expression) in savedata.current_characters {
-                let character =
-                    load_character_tween(ctx, self.resources, name, expression, Placement::Left)
-                        .unwrap();
-                self.screen
-                    .current_characters
-                    .current
-                    .push(Box::new(character));
+        self.open_save_window(ctx, SaveWindowMode::Load, None);
+    }
+
+    /// Writes the lines read since the last save to read.json, on every save including the
+    /// autosaves at scene changes and when leaving the game.
+    fn flush_read_history(&self, ctx: &mut Context) {
+        let config = self.resources.get_config();
+        config.read.borrow_mut().flush(ctx, &config.short_game_name);
+    }
+
+    pub fn save_to_slot(
+        &mut self,
+        ctx: &mut Context,
+        slot: SlotId,
+        thumbnail: Option<&image::RgbaImage>,
+    ) {
+        self.flush_read_history(ctx);
+        info!("Saving game to slot {:?}", slot);
+        match crate::save::write_save(
+            ctx,
+            &self.resources.get_config().short_game_name,
+            slot,
+            &self.save_data(),
+            thumbnail,
+        ) {
+            Ok(()) => info!("Saved game!"),
+            Err(e) => warn!("Unable to save to slot {:?}: {}", slot, e),
+        }
+    }
+
+    pub fn quick_save(&mut self, ctx: &mut Context) {
+        let thumbnail = crate::save::capture_thumbnail(ctx)
+            .map_err(|e| warn!("Unable to capture save thumbnail: {}", e))
+            .ok();
+        self.save_to_slot(ctx, SlotId::Quick, thumbnail.as_ref());
+    }
+
+    fn write_autosave(&mut self, ctx: &mut Context) {
+        self.autosave = AutosaveState::None;
+        let thumbnail = crate::save::capture_thumbnail(ctx)
+            .map_err(|e| warn!("Unable to capture save thumbnail: {}", e))
+            .ok();
+        let slot = SlotId::Auto(self.next_autosave);
+        self.next_autosave = (self.next_autosave + 1) % AUTOSAVE_COUNT;
+        self.save_to_slot(ctx, slot, thumbnail.as_ref());
+    }
+
+    pub fn load_from_slot(&mut self, ctx: &mut Context, slot: SlotId) {
+        info!("Loading game from slot {:?}", slot);
+        match crate::save::read_save(ctx, &self.resources.get_config().short_game_name, slot) {
+            Ok(savedata) => {
+                if let Err(e) = self.restore_stage(
+                    ctx,
+                    savedata.current_background,
+                    savedata.current_characters,
+                    savedata.sounds,
+                    savedata.nvl,
+                    savedata.camera,
+                ) {
+                    self.show_load_error(ctx, slot, &e);
+                    return;
+                }
+                self.state = savedata.state;
+                self.playtime = savedata.meta.playtime;
+                self.last_line = savedata.meta.last_line;
+                self.continue_method = savedata.continue_method;
+                self.restore_weather(ctx, savedata.weather);
+                self.screen.filter.reset(savedata.filter);
+                self.rollback.clear();
+                self.backlog = savedata.backlog;
+                // Saves from before the backlog was limited can have more
+                let excess = self.backlog.len().saturating_sub(MAX_BACKLOG);
+                self.backlog.drain(..excess);
+
+                if let Err(e) = self.continue_text(ctx, false) {
+                    self.show_load_error(ctx, slot, &SaveError::Incompatible(e.to_string()));
+                }
+                self.restored = true;
+                info!("Loaded game!");
             }
-            if let Some(name) = savedata.current_background {
-                let background = load_background_tween(ctx, self.resources, None, name).unwrap();
-                self.screen.current_background = Some(BackgroundContainer {
-                    current: Box::new(background),
+            Err(e) => self.show_load_error(ctx, slot, &e),
+        }
+    }
+
+    /// Logs why `slot` couldn't be loaded and tells the player, quick loads have no window
+    /// that would show it otherwise.
+    fn show_load_error(&mut self, ctx: &mut Context, slot: SlotId, e: &SaveError) {
+        warn!("Unable to load slot {:?}: {}", slot, e);
+        let message = match e {
+            SaveError::NotFound => "There's no save to load",
+            SaveError::Corrupt(_) => "This save is corrupt",
+            SaveError::Incompatible(_) => "This save is incompatible with this version",
+        };
+        self.screen.notice = Some(Notice::new(ctx, message));
+    }
+
+    fn on_save_window_click(&mut self, ctx: &mut Context, x: f32, y: f32) {
+        let (mode, clicked_slot, is_loadable, exit) =
+            if let Window::Save(window) = &self.screen.window {
+                let clicked = window.slots.children.iter().find_map(|(slot, n)| {
+                    if slot.button.click_event(ctx, x, y) {
+                        Some((*n, slot.is_loadable))
+                    } else {
+                        None
+                    }
                 });
+                (
+                    window.mode,
+                    clicked.map(|(n, _)| n),
+                    clicked.map(|(_, is_loadable)| is_loadable).unwrap_or(false),
+                    window.exit_button.click_event(ctx, x, y),
+                )
             } else {
-                self.screen.current_background = None;
-            }
+                return;
+            };
 
-            self.continue_text(ctx, false).unwrap();
-            println!("Loaded game!");
-        } else {
-            println!("Unable to find save file");
+        if exit {
+            self.screen.window = Window::None;
+        } else if let Some(slot) = clicked_slot {
+            match mode {
+                SaveWindowMode::Save => {
+                    let thumbnail = match std::mem::replace(&mut self.screen.window, Window::None) {
+                        Window::Save(window) => window.thumbnail,
+                        _ => None,
+                    };
+                    self.save_to_slot(ctx, slot, thumbnail.as_ref());
+                    // Reopen to show the updated slot
+                    self.open_save_window(ctx, SaveWindowMode::Save, thumbnail);
+                }
+                SaveWindowMode::Load => {
+                    if is_loadable {
+                        self.screen.window = Window::None;
+                        self.load_from_slot(ctx, slot);
+                    }
+                }
+            }
         }
     }
 
     fn advance_text(&mut self, ctx: &mut Context) {
+        if self.screen.window.is_open() {
+            return;
+        }
         if let Action::Text(text) = &mut self.screen.action {
             if self.continue_method == ContinueMethod::Normal {
                 if text.content.content.is_done() {
                     self.continue_text(ctx, true).unwrap();
                 } else {
                     text.content.content.finish();
+                    self.screen.finish_transitions();
                 }
             }
         }
@@ -270,8 +1131,14 @@ impl GameState {
 }
 
 impl StateEventHandler for GameState {
+    fn quit_event(&mut self, ctx: &mut Context) -> bool {
+        self.flush_read_history(ctx);
+        false
+    }
+
     fn change_state(&mut self, ctx: &mut Context) -> Option<super::State> {
         if self.is_end {
+            self.flush_read_history(ctx);
             Some(super::State::MainMenu(super::MainMenuState::new(
                 ctx,
                 self.resources,
@@ -283,24 +1150,50 @@ impl StateEventHandler for GameState {
 
     fn update(&mut self, ctx: &mut Context) -> ggez::GameResult {
         let dt = ggez::timer::delta(ctx).as_secs_f32();
-        if let Action::Text(textbox) = &self.screen.action {
+        self.playtime += dt;
+        if self.autosave == AutosaveState::Drawn {
+            self.write_autosave(ctx);
+        }
+        if self.screen.window.is_open() {
+            // Don't advance the text while the player is looking at a window
+        } else if let Action::Text(textbox) = &self.screen.action {
             match self.continue_method {
+                ContinueMethod::Skip(..)
+                    if !self.line_is_read
+                        && self.resources.get_config().user.borrow().skip_mode
+                            == SkipMode::ReadOnly =>
+                {
+                    self.continue_method = ContinueMethod::Normal;
+                }
                 ContinueMethod::Skip(ref mut n) => {
                     *n += dt;
-                    if *n >= 0.1 {
+                    if *n >= self.resources.get_config().user.borrow().skip_delay {
                         *n = 0.0;
                         self.continue_text(ctx, true)?;
+                        self.screen.finish_transitions();
                     }
                 }
                 ContinueMethod::Auto(ref mut n) => {
                     if textbox.content.content.is_done() {
                         *n += dt;
-                        if *n >= 1.0 {
+                        let char_count =
+                            textbox.content.content.get_current().text.fragments().len();
+                        if *n
+                            >= self
+                                .resources
+                                .get_config()
+                                .user
+                                .borrow()
+                                .auto_delay_for(char_count)
+                        {
                             *n = 0.0;
                             self.continue_text(ctx, true)?;
                         }
                     }
                 }
+                _ if textbox.no_wait && textbox.content.content.is_done() && !self.restored => {
+                    self.continue_text(ctx, true)?;
+                }
                 _ => {}
             }
         } else if let Action::Choice(..) = self.screen.action {
@@ -310,41 +1203,58 @@ impl StateEventHandler for GameState {
         }
         self.screen.update(dt);
         let config = self.resources.get_config();
-        if let Some(audio) = &mut self.audio.music {
-            audio.set_volume(
-                config.user.borrow().master_volume
-                    * config.user.borrow().channel_volumes.0["music"],
-            );
-            if !audio.playing() {
-                audio.play(ctx)?;
+        if let Action::Text(textbox) = &mut self.screen.action {
+            let revealed = textbox.revealed();
+            let is_skipping = matches!(self.continue_method, ContinueMethod::Skip(..));
+            if let Some(voice) = &textbox.voice {
+                if revealed > 0 && (revealed - 1) / BLIP_INTERVAL >= textbox.blipped && !is_skipping
+                {
+                    textbox.blipped = (revealed - 1) / BLIP_INTERVAL + 1;
+                    let mut blip = self
+                        .resources
+                        .get_sound_source(ctx, &format!("/audio/{}", voice))?;
+                    blip.set_volume(
+                        config.user.borrow().master_volume
+                            * config.user.borrow().channel_volumes.volume("voice"),
+                    );
+                    blip.play_detached(ctx)?;
+                }
             }
         }
+        self.audio.update(dt, &config.user.borrow());
         if let Some(audio) = self.audio.ui_sfx.borrow_mut().as_mut() {
             audio.set_volume(
-                config.user.borrow().master_volume * config.user.borrow().channel_volumes.0["sfx"],
+                config.user.borrow().master_volume
+                    * config.user.borrow().channel_volumes.volume("sfx"),
             );
             // Check elapsed time to make sure we aren't trying to replay a ui sfx
             if !audio.playing() && audio.elapsed().as_millis() < 1 {
                 audio.play(ctx)?;
             }
         }
-        if let Some(audio) = &mut self.audio.sfx {
-            audio.set_volume(
-                config.user.borrow().master_volume * config.user.borrow().channel_volumes.0["sfx"],
-            );
-            if !audio.playing() {
-                audio.play(ctx)?;
-            }
-        }
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context, param: DrawParam) -> ggez::GameResult {
         self.screen.draw(ctx, param)?;
+        if self.autosave == AutosaveState::Pending {
+            self.autosave = AutosaveState::Drawn;
+        }
         Ok(())
     }
 
     fn key_down_event(&mut self, ctx: &mut Context, key: KeyCode, _mods: KeyMods, _: bool) {
+        if self.screen.window.is_open() {
+            if key == KeyCode::Escape {
+                self.screen.window = Window::None;
+            }
+            return;
+        }
+        match key {
+            KeyCode::F5 => self.quick_save(ctx),
+            KeyCode::F9 => self.load_from_slot(ctx, SlotId::Quick),
+            _ => (),
+        }
         if let Action::Text(..) = &self.screen.action {
             match key {
                 KeyCode::Space | KeyCode::Return => {
@@ -353,23 +1263,62 @@ impl StateEventHandler for GameState {
                 KeyCode::H => {
                     self.screen.is_screenshot = !self.screen.is_screenshot;
                 }
+                KeyCode::L => self.open_backlog_window(ctx),
+                KeyCode::PageUp => {
+                    self.roll_back(ctx);
+                }
+                KeyCode::PageDown => self.roll_forward(ctx),
                 _ => (),
             }
         }
     }
 
+    fn mouse_wheel_event(&mut self, ctx: &mut Context, _x: f32, y: f32) {
+        if let Window::Backlog(window) = &mut self.screen.window {
+            if y > 0.0 {
+                window.scroll_up();
+            } else if y < 0.0 && !window.scroll_down() {
+                self.screen.window = Window::None;
+            }
+            return;
+        }
+        if self.screen.window.is_open() {
+            return;
+        }
+        if y > 0.0 {
+            // Once there's nothing left to roll back, show the backlog instead
+            if !self.roll_back(ctx) {
+                self.open_backlog_window(ctx);
+            }
+        } else if y < 0.0 {
+            self.roll_forward(ctx);
+        }
+    }
+
     fn text_input_event(&mut self, ctx: &mut Context, ch: char) {
-        if let Action::Choice(choices) = &mut self.screen.action {
+        if self.screen.window.is_open() {
+            return;
+        }
+        if let Action::Choice(choices) = &self.screen.action {
             if let Some(n) = ch.to_digit(10) {
-                if n >= 1 && n < choices.children.len() as u32 {
-                    self.state.set_choice(n as i32);
-                    self.continue_text(ctx, true).unwrap();
+                if n >= 1 && n <= choices.children.len() as u32 {
+                    self.choose(ctx, n - 1);
                 }
             }
         }
     }
 
     fn mouse_motion_event(&mut self, ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) {
+        if let Window::Save(window) = &mut self.screen.window {
+            window.exit_button.mouse_motion_event(ctx, x, y);
+            for (slot, _) in &mut window.slots.children {
+                slot.button.mouse_motion_event(ctx, x, y);
+            }
+            return;
+        } else if let Window::Backlog(window) = &mut self.screen.window {
+            window.exit_button.mouse_motion_event(ctx, x, y);
+            return;
+        }
         if let Action::Choice(choices) = &mut self.screen.action {
             for (button, _) in &mut choices.children {
                 button.mouse_motion_event(ctx, x, y);
@@ -382,17 +1331,46 @@ impl StateEventHandler for GameState {
     }
 
     fn mouse_button_up_event(&mut self, ctx: &mut Context, _button: MouseButton, x: f32, y: f32) {
+        if let Window::Save(..) = self.screen.window {
+            self.on_save_window_click(ctx, x, y);
+            return;
+        } else if let Window::Backlog(window) = &self.screen.window {
+            if window.exit_button.click_event(ctx, x, y) {
+                self.screen.window = Window::None;
+            }
+            return;
+        } else if let Window::Glossary(..) = self.screen.window {
+            self.screen.window = Window::None;
+            return;
+        }
         let mut clicked_anything = false;
+        if let Action::Text(textbox) = &self.screen.action {
+            // Only links that have been shown can be clicked
+            if textbox.content.content.is_done() {
+                match textbox.format_at(ctx, x, y).cloned() {
+                    Some(Format::Link(url)) => {
+                        if let Err(e) = webbrowser::open(&url) {
+                            warn!("Unable to open {}: {}", url, e);
+                        }
+                        clicked_anything = true;
+                    }
+                    Some(Format::Glossary(key)) => {
+                        self.open_glossary_window(ctx, &key);
+                        clicked_anything = true;
+                    }
+                    None => {}
+                }
+            }
+        }
         if let Action::Choice(container) = &self.screen.action {
             if let Some(n) = container.children.iter().find_map(|(button, n)| {
                 if button.click_event(ctx, x, y) {
-                    Some(n)
+                    Some(*n)
                 } else {
                     None
                 }
             }) {
-                self.state.set_choice(*n as i32 + 1);
-                self.continue_text(ctx, true).unwrap();
+                self.choose(ctx, n);
                 clicked_anything = true;
             }
         }
@@ -407,6 +1385,7 @@ impl StateEventHandler for GameState {
             match e {
                 MenuButtonId::Save => self.on_save_click(ctx),
                 MenuButtonId::Load => self.on_load_click(ctx),
+                MenuButtonId::Backlog => self.open_backlog_window(ctx),
                 MenuButtonId::Skip => {
                     self.continue_method = if let ContinueMethod::Skip(..) = self.continue_method {
                         ContinueMethod::Normal
@@ -1,6 +1,10 @@
 use std::{cell::RefCell, io::Read, path::PathBuf, rc::Rc};
 
 use crate::{
+    config::{
+        progress_to_range, range_to_progress, AudioConfig, SkipMode, AUTO_DELAY_RANGE,
+        SKIP_DELAY_RANGE, TEXT_SPEED_RANGE,
+    },
     containers::{
         button::Button,
         config_window::{ConfigWindow, VolumeControl},
@@ -30,6 +34,9 @@ use graphics::{FillOptions, Rect, Text};
 
 use super::{game::GameState, State, StateEventHandler};
 
+// Volume sliders in a column of the options window
+const VOLUME_ROWS: usize = 8;
+
 pub struct MainMenuState {
     pub resources: &'static ResourceManager,
     pub screen: MainMenuScreen,
@@ -115,6 +122,31 @@ impl MainMenuState {
     }
 }
 
+fn skip_mode_button(
+    ctx: &mut Context,
+    resources: &'static ResourceManager,
+    ui_sfx: Rc<RefCell<Option<ggez::audio::Source>>>,
+) -> Button {
+    let skip_mode = resources.get_config().user.borrow().skip_mode;
+    Button::new(
+        ctx,
+        resources,
+        Rect {
+            x: crate::helpers::target_size().x / 2.0 + 20.0,
+            y: crate::helpers::target_size().y / 2.0 + (46.0 * 3.0) / 2.0 + 20.0,
+            w: 240.0,
+            h: 40.0,
+        },
+        match skip_mode {
+            SkipMode::ReadOnly => "Skip: Read text only",
+            SkipMode::All => "Skip: All text",
+        }
+        .into(),
+        ui_sfx,
+    )
+    .unwrap()
+}
+
 impl StateEventHandler for MainMenuState {
     fn change_state(&mut self, ctx: &mut Context) -> Option<State> {
         if let Some(MenuButtonId::Start) = self.clicked_event {
@@ -143,6 +175,10 @@ impl StateEventHandler for MainMenuState {
             match e {
                 MenuButtonId::Start => {} // Handled in change_state
                 MenuButtonId::Options => {
+                    // Master and every channel, in a second column once the first is full
+                    let volume_count = config.audio.channels.len() + 1;
+                    let volume_columns = (volume_count + VOLUME_ROWS - 1) / VOLUME_ROWS;
+                    let volume_rows = (volume_count + volume_columns - 1) / volume_columns;
                     let mut config_window = ConfigWindow {
                         panel: graphics::Mesh::new_rectangle(
                             ctx,
@@ -173,27 +209,89 @@ impl StateEventHandler for MainMenuState {
                         )
                         .unwrap(),
                         volume_controls: StackContainer::new(
+                            Position::Center.add_in(
+                                ctx,
+                                glam::Vec2::new(
+                                    -20.0 - 245.0 * volume_columns as f32 + 5.0,
+                                    (-51.0 * volume_rows as f32 + 5.0) / 2.0,
+                                ),
+                            ),
+                            5.0,
+                            (240.0, 46.0),
+                            Direction::Grid {
+                                columns: volume_columns,
+                            },
+                        ),
+                        text_controls: StackContainer::new(
                             Position::Center
-                                .add_in(ctx, glam::Vec2::new(-120.0, (-46.0 * 3.0) / 2.0)),
+                                .add_in(ctx, glam::Vec2::new(20.0, (-46.0 * 3.0) / 2.0)),
                             5.0,
                             (240.0, 46.0),
                             Direction::Vertical,
                         ),
+                        skip_mode_button: skip_mode_button(
+                            ctx,
+                            self.resources,
+                            self.ui_sfx.clone(),
+                        ),
                     };
+                    // A slider for every channel, including the ones from engine.ini
+                    let volumes = std::iter::once((
+                        "Master".to_owned(),
+                        config.user.borrow().master_volume,
+                        "master".to_owned(),
+                    ))
+                    .chain(config.audio.channels.iter().map(|channel| {
+                        (
+                            AudioConfig::label(channel),
+                            config.user.borrow().channel_volumes.volume(channel),
+                            channel.clone(),
+                        )
+                    }));
+                    for (n, (d, v, s)) in volumes.enumerate() {
+                        let rect = config_window.volume_controls.get_rect_for(n as f32);
+                        config_window.volume_controls.children.push((
+                            VolumeControl(
+                                Sprite {
+                                    content: Text::new(d),
+                                    param: DrawParam::new().dest(rect.point()),
+                                },
+                                Slider::new(
+                                    ctx,
+                                    Rect {
+                                        x: rect.x,
+                                        y: rect.y + 16.0,
+                                        w: rect.w,
+                                        h: rect.h - 16.0,
+                                    },
+                                    v,
+                                ),
+                            ),
+                            s,
+                        ))
+                    }
                     for (n, &(d, v, s)) in [
-                        ("Master", config.user.borrow().master_volume, "master"),
-                        ("SFX", config.user.borrow().channel_volumes.0["sfx"], "sfx"),
                         (
-                            "BGM",
-                            config.user.borrow().channel_volumes.0["music"],
-                            "music",
+                            "Text speed",
+                            range_to_progress(config.user.borrow().text_speed, TEXT_SPEED_RANGE),
+                            "text_speed",
+                        ),
+                        (
+                            "Auto delay",
+                            range_to_progress(config.user.borrow().auto_delay, AUTO_DELAY_RANGE),
+                            "auto_delay",
+                        ),
+                        (
+                            "Skip delay",
+                            range_to_progress(config.user.borrow().skip_delay, SKIP_DELAY_RANGE),
+                            "skip_delay",
                         ),
                     ]
                     .iter()
                     .enumerate()
                     {
-                        let rect = config_window.volume_controls.get_rect_for(n as f32);
-                        config_window.volume_controls.children.push((
+                        let rect = config_window.text_controls.get_rect_for(n as f32);
+                        config_window.text_controls.children.push((
                             VolumeControl(
                                 Sprite {
                                     content: Text::new(d),
@@ -272,14 +370,16 @@ impl StateEventHandler for MainMenuState {
             }
         }
         self.music.set_volume(
-            config.user.borrow().master_volume * config.user.borrow().channel_volumes.0["music"],
+            config.user.borrow().master_volume
+                * config.user.borrow().channel_volumes.volume("music"),
         );
         if !self.music.playing() {
             self.music.play(ctx)?;
         }
         if let Some(audio) = self.ui_sfx.borrow_mut().as_mut() {
             audio.set_volume(
-                config.user.borrow().master_volume * config.user.borrow().channel_volumes.0["sfx"],
+                config.user.borrow().master_volume
+                    * config.user.borrow().channel_volumes.volume("sfx"),
             );
             if !audio.playing() && audio.elapsed().as_millis() < 1 {
                 audio.play(ctx)?;
@@ -300,18 +400,31 @@ impl StateEventHandler for MainMenuState {
             }
         } else if let Window::Options(window) = &mut self.screen.window {
             window.exit_button.mouse_motion_event(ctx, x, y);
+            window.skip_mode_button.mouse_motion_event(ctx, x, y);
             for (slider, d) in &mut window.volume_controls.children {
                 if let Some(n) = slider.1.mouse_motion_event(ctx, x, y, dx, dy) {
                     let config = self.resources.get_config();
                     let mut config = config.user.borrow_mut();
-                    match *d {
+                    match d.as_str() {
                         "master" => config.master_volume = n,
                         s => {
-                            *config.channel_volumes.0.get_mut(s).unwrap() = n;
+                            config.channel_volumes.0.insert(s.to_owned(), n);
                         }
                     }
                 }
             }
+            for (slider, d) in &mut window.text_controls.children {
+                if let Some(n) = slider.1.mouse_motion_event(ctx, x, y, dx, dy) {
+                    let config = self.resources.get_config();
+                    let mut config = config.user.borrow_mut();
+                    match *d {
+                        "text_speed" => config.text_speed = progress_to_range(n, TEXT_SPEED_RANGE),
+                        "auto_delay" => config.auto_delay = progress_to_range(n, AUTO_DELAY_RANGE),
+                        "skip_delay" => config.skip_delay = progress_to_range(n, SKIP_DELAY_RANGE),
+                        _ => unreachable!(),
+                    }
+                }
+            }
         } else if let Window::Credits(window) = &mut self.screen.window {
             window.exit_button.mouse_motion_event(ctx, x, y);
         }
@@ -322,6 +435,9 @@ impl StateEventHandler for MainMenuState {
             for (slider, _) in &mut window.volume_controls.children {
                 slider.1.mouse_button_down_event(ctx, button, x, y);
             }
+            for (slider, _) in &mut window.text_controls.children {
+                slider.1.mouse_button_down_event(ctx, button, x, y);
+            }
         }
     }
 
@@ -340,6 +456,21 @@ impl StateEventHandler for MainMenuState {
             for (slider, _) in &mut window.volume_controls.children {
                 slider.1.mouse_button_up_event(ctx, button, x, y);
             }
+            for (slider, _) in &mut window.text_controls.children {
+                slider.1.mouse_button_up_event(ctx, button, x, y);
+            }
+            if window.skip_mode_button.click_event(ctx, x, y) {
+                {
+                    let config = self.resources.get_config();
+                    let mut config = config.user.borrow_mut();
+                    config.skip_mode = match config.skip_mode {
+                        SkipMode::ReadOnly => SkipMode::All,
+                        SkipMode::All => SkipMode::ReadOnly,
+                    };
+                }
+                window.skip_mode_button =
+                    skip_mode_button(ctx, self.resources, self.ui_sfx.clone());
+            }
             if window.exit_button.click_event(ctx, x, y) {
                 self.resources
                     .get_config()
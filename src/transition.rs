@@ -0,0 +1,449 @@
+use crate::placement::PlacementChange;
+
+/// How a character enters or leaves the stage.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Transition {
+    Instant,
+    Fade,
+    // Slides from or towards the left edge of the screen
+    SlideLeft,
+    SlideRight,
+}
+
+impl Transition {
+    fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s {
+            "instant" | "none" => Transition::Instant,
+            "fade" => Transition::Fade,
+            "slide-left" => Transition::SlideLeft,
+            "slide-right" => Transition::SlideRight,
+            _ => return Err(format!("unknown transition `{}`", s)),
+        })
+    }
+
+    /// Horizontal offset the character is at when it's off stage.
+    pub fn offstage_offset(&self, center_x: f32, width: f32) -> f32 {
+        let screen_width = crate::helpers::target_size().x;
+        match self {
+            Transition::SlideLeft => -(center_x + width / 2.0),
+            Transition::SlideRight => screen_width - center_x + width / 2.0,
+            Transition::Instant | Transition::Fade => 0.0,
+        }
+    }
+}
+
+/// Played on a character that's already on stage.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Effect {
+    Hop,
+    Shake,
+}
+
+impl Effect {
+    fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s {
+            "hop" => Effect::Hop,
+            "shake" => Effect::Shake,
+            _ => return Err(format!("unknown effect `{}`", s)),
+        })
+    }
+
+    pub fn duration(&self) -> f32 {
+        match self {
+            Effect::Hop => 0.4,
+            Effect::Shake => 0.5,
+        }
+    }
+
+    pub fn offset(&self, progress: f32) -> glam::Vec2 {
+        match self {
+            Effect::Hop => glam::Vec2::new(0.0, -40.0 * (progress * std::f32::consts::PI).sin()),
+            Effect::Shake => glam::Vec2::new(
+                15.0 * (progress * std::f32::consts::PI * 8.0).sin() * (1.0 - progress),
+                0.0,
+            ),
+        }
+    }
+}
+
+/// Eases in and out so movement doesn't start and stop abruptly.
+pub fn smoothstep(progress: f32) -> f32 {
+    progress * progress * (3.0 - 2.0 * progress)
+}
+
+/// Alpha and horizontal offset of a character over the course of a transition.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Motion {
+    pub duration: f32,
+    pub alpha: (f32, f32),
+    pub offset_x: (f32, f32),
+    pub effect: Option<Effect>,
+    // Seconds to crossfade from the previous expression
+    pub swap: f32,
+}
+
+impl Default for Motion {
+    fn default() -> Self {
+        Self {
+            duration: 0.0,
+            alpha: (1.0, 1.0),
+            offset_x: (0.0, 0.0),
+            effect: None,
+            swap: 0.0,
+        }
+    }
+}
+
+impl Motion {
+    pub fn enter(transition: Transition, center_x: f32, width: f32) -> Self {
+        match transition {
+            Transition::Instant => Motion::default(),
+            Transition::Fade => Motion {
+                duration: 0.75,
+                alpha: (0.0, 1.0),
+                ..Motion::default()
+            },
+            Transition::SlideLeft | Transition::SlideRight => Motion {
+                duration: 0.75,
+                offset_x: (transition.offstage_offset(center_x, width), 0.0),
+                ..Motion::default()
+            },
+        }
+    }
+
+    pub fn exit(transition: Transition, center_x: f32, width: f32) -> Self {
+        match transition {
+            Transition::Instant => Motion::default(),
+            Transition::Fade => Motion {
+                duration: 0.5,
+                alpha: (1.0, 0.0),
+                ..Motion::default()
+            },
+            Transition::SlideLeft | Transition::SlideRight => Motion {
+                duration: 0.5,
+                offset_x: (0.0, transition.offstage_offset(center_x, width)),
+                ..Motion::default()
+            },
+        }
+    }
+
+    /// The exit `self` started from the `alpha` and `offset_x` a character is at, so one
+    /// removed during its entrance leaves from where it got to instead of jumping back.
+    pub fn starting_from(self, alpha: f32, offset_x: f32) -> Self {
+        Motion {
+            alpha: (alpha, self.alpha.1.min(alpha)),
+            // Exits that don't slide keep the character where it is
+            offset_x: (
+                offset_x,
+                if self.offset_x.1 == 0.0 {
+                    offset_x
+                } else {
+                    self.offset_x.1
+                },
+            ),
+            ..self
+        }
+    }
+
+    /// For a character already on stage, moving from `from_x` pixels away from its placement
+    /// and crossfading from its previous expression for `swap` seconds.
+    pub fn stay(from_x: f32, effect: Option<Effect>, swap: f32) -> Self {
+        let move_duration = if from_x == 0.0 { 0.0 } else { 0.5 };
+        Motion {
+            duration: effect
+                .map_or(move_duration, |e| e.duration().max(move_duration))
+                .max(swap),
+            offset_x: (from_x, 0.0),
+            effect,
+            swap,
+            ..Motion::default()
+        }
+    }
+
+    /// How far the crossfade to the new expression is at `progress` of the whole motion.
+    pub fn swap_progress(&self, progress: f32) -> f32 {
+        if self.swap > 0.0 {
+            (progress * self.duration / self.swap).min(1.0)
+        } else {
+            1.0
+        }
+    }
+
+    /// Returns the alpha and offset at `progress` between 0 and 1.
+    pub fn apply(&self, progress: f32) -> (f32, glam::Vec2) {
+        let alpha = self.alpha.0 + (self.alpha.1 - self.alpha.0) * progress;
+        let x = self.offset_x.0 + (self.offset_x.1 - self.offset_x.0) * smoothstep(progress);
+        let effect = self
+            .effect
+            .map_or(glam::Vec2::zero(), |effect| effect.offset(progress));
+        (alpha, glam::Vec2::new(x, 0.0) + effect)
+    }
+}
+
+/// The argument of a character command, a [`PlacementChange`] along with
+/// `enter=`, `exit=`, `move=`, `swap=` and `effect=` tokens.
+#[derive(Debug, Default, PartialEq)]
+pub struct CharacterDirections {
+    pub placement: Option<PlacementChange>,
+    pub enter: Option<Transition>,
+    pub exit: Option<Transition>,
+    // Whether to slide to a new placement, `move=instant` jumps there
+    pub animate_move: Option<bool>,
+    pub effect: Option<Effect>,
+    // Whether to crossfade to a new expression, `swap=instant` replaces it at once
+    pub animate_swap: Option<bool>,
+}
+
+impl CharacterDirections {
+    pub fn parse(v: &str) -> Result<Self, String> {
+        let mut directions = CharacterDirections::default();
+        let mut placement = Vec::new();
+        for token in v
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|s| !s.is_empty())
+        {
+            let lower = token.to_lowercase();
+            if let Some(enter) = lower.strip_prefix("enter=") {
+                directions.enter = Some(Transition::parse(enter)?);
+            } else if let Some(exit) = lower.strip_prefix("exit=") {
+                directions.exit = Some(Transition::parse(exit)?);
+            } else if let Some(effect) = lower.strip_prefix("effect=") {
+                directions.effect = Some(Effect::parse(effect)?);
+            } else if let Some(animate) = lower.strip_prefix("move=") {
+                directions.animate_move = Some(match animate {
+                    "slide" => true,
+                    "instant" => false,
+                    _ => return Err(format!("unknown move `{}`", animate)),
+                });
+            } else if let Some(animate) = lower.strip_prefix("swap=") {
+                directions.animate_swap = Some(match animate {
+                    "fade" => true,
+                    "instant" => false,
+                    _ => return Err(format!("unknown swap `{}`", animate)),
+                });
+            } else {
+                placement.push(token);
+            }
+        }
+        if !placement.is_empty() {
+            directions.placement = Some(PlacementChange::parse(&placement.join(" "))?);
+        }
+        Ok(directions)
+    }
+}
+
+/// Splits the argument of a remove command, `Name` or `Name slide-left`.
+pub fn parse_remove(v: &str) -> Result<(&str, Option<Transition>), String> {
+    let mut tokens = v.split_whitespace();
+    let name = tokens.next().unwrap_or_default();
+    let transition = tokens
+        .next()
+        .map(|token| Transition::parse(&token.to_lowercase()))
+        .transpose()?;
+    Ok((name, transition))
+}
+
+/// The direction a background wipe, slide or push moves in.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl Direction {
+    fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s {
+            "left" => Direction::Left,
+            "right" => Direction::Right,
+            "up" => Direction::Up,
+            "down" => Direction::Down,
+            _ => return Err(format!("unknown direction `{}`", s)),
+        })
+    }
+
+    pub fn vector(&self) -> glam::Vec2 {
+        match self {
+            Direction::Left => glam::Vec2::new(-1.0, 0.0),
+            Direction::Right => glam::Vec2::new(1.0, 0.0),
+            Direction::Up => glam::Vec2::new(0.0, -1.0),
+            Direction::Down => glam::Vec2::new(0.0, 1.0),
+        }
+    }
+}
+
+/// How the scene command changes from one background to the next.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackgroundTransition {
+    Instant,
+    // The new background fades in over the previous one
+    Dissolve,
+    FadeBlack,
+    // The new background is uncovered by an edge moving across the screen
+    Wipe(Direction),
+    // The new background moves in over the previous one
+    Slide(Direction),
+    // The new background moves in and pushes the previous one out
+    Push(Direction),
+    // Grayscale image in /transitions/, darker pixels are revealed first
+    Rule(String),
+}
+
+impl BackgroundTransition {
+    fn parse(token: &str) -> Result<Self, String> {
+        let s = token.to_lowercase();
+        // Rule images are files, their names keep their case
+        if s.starts_with("rule=") {
+            return Ok(BackgroundTransition::Rule(
+                token["rule=".len()..].to_owned(),
+            ));
+        }
+        Ok(match s.as_str() {
+            "instant" | "none" => BackgroundTransition::Instant,
+            "dissolve" | "fade" => BackgroundTransition::Dissolve,
+            "fade-black" | "fadeblack" => BackgroundTransition::FadeBlack,
+            _ => {
+                let mut parts = s.splitn(2, '-');
+                let kind = parts.next().unwrap_or_default();
+                let direction = parts
+                    .next()
+                    .ok_or_else(|| format!("unknown transition `{}`", s))?;
+                let direction = Direction::parse(direction)?;
+                match kind {
+                    "wipe" => BackgroundTransition::Wipe(direction),
+                    "slide" => BackgroundTransition::Slide(direction),
+                    "push" => BackgroundTransition::Push(direction),
+                    _ => return Err(format!("unknown transition `{}`", s)),
+                }
+            }
+        })
+    }
+
+    /// Seconds the transition takes if the scene command doesn't say.
+    pub fn default_duration(&self) -> f32 {
+        match self {
+            BackgroundTransition::Instant => 0.0,
+            BackgroundTransition::Dissolve => 0.5,
+            BackgroundTransition::FadeBlack | BackgroundTransition::Rule(..) => 1.0,
+            BackgroundTransition::Wipe(..)
+            | BackgroundTransition::Slide(..)
+            | BackgroundTransition::Push(..) => 0.75,
+        }
+    }
+}
+
+// Fraction of a rule image that is partially revealed at once, softens the edge
+const RULE_SOFTNESS: f32 = 0.1;
+
+/// Alpha of a pixel of the new background whose rule image value is `mask`, between 0 and 1.
+pub fn rule_alpha(mask: f32, progress: f32) -> f32 {
+    ((progress * (1.0 + RULE_SOFTNESS) - mask) / RULE_SOFTNESS)
+        .max(0.0)
+        .min(1.0)
+}
+
+/// Splits the argument of a scene command, e.g. `Bridge`, `Bridge wipe-left`
+/// or `Bridge rule=clock 1.5s`.
+pub fn parse_scene(v: &str) -> Result<(&str, BackgroundTransition, f32), String> {
+    let mut tokens = v.split_whitespace();
+    let name = tokens.next().unwrap_or_default();
+    let mut transition = None;
+    let mut duration = None;
+    for token in tokens {
+        let lower = token.to_lowercase();
+        if let Ok(seconds) = lower.trim_end_matches('s').parse::<f32>() {
+            if !seconds.is_finite() || seconds < 0.0 {
+                return Err(format!("`{}` is not a duration", token));
+            }
+            duration = Some(seconds);
+        } else if transition.is_none() {
+            transition = Some(BackgroundTransition::parse(token)?);
+        } else {
+            return Err(format!("unexpected `{}`", token));
+        }
+    }
+    let transition = transition.unwrap_or(BackgroundTransition::Dissolve);
+    let duration = duration.unwrap_or_else(|| transition.default_duration());
+    Ok((name, transition, duration))
+}
+
+#[test]
+fn test_character_directions() {
+    let directions = CharacterDirections::parse("far-left enter=slide-left effect=hop").unwrap();
+    assert_eq!(
+        directions.placement,
+        Some(PlacementChange::parse("far-left").unwrap())
+    );
+    assert_eq!(directions.enter, Some(Transition::SlideLeft));
+    assert_eq!(directions.effect, Some(Effect::Hop));
+    assert_eq!(
+        CharacterDirections::parse("effect=shake")
+            .unwrap()
+            .placement,
+        None
+    );
+    assert!(CharacterDirections::parse("enter=teleport").is_err());
+    assert_eq!(
+        parse_remove("Yukio slide-right").unwrap(),
+        ("Yukio", Some(Transition::SlideRight))
+    );
+    assert_eq!(parse_remove("Yukio").unwrap(), ("Yukio", None));
+
+    let motion = Motion::stay(100.0, Some(Effect::Hop), 0.25);
+    assert_eq!(motion.duration, 0.5);
+    assert_eq!(motion.swap_progress(0.25), 0.5);
+    assert_eq!(motion.swap_progress(0.75), 1.0);
+    assert_eq!(motion.apply(0.0), (1.0, glam::Vec2::new(100.0, 0.0)));
+    assert_eq!(motion.apply(1.0).1.x, 0.0);
+}
+
+#[test]
+fn test_exit_starting_from() {
+    let fade = Motion::exit(Transition::Fade, 640.0, 200.0).starting_from(0.4, -80.0);
+    assert_eq!(fade.alpha, (0.4, 0.0));
+    assert_eq!(fade.offset_x, (-80.0, -80.0));
+    let slide = Motion::exit(Transition::SlideLeft, 640.0, 200.0);
+    let offstage = slide.offset_x.1;
+    let slide = slide.starting_from(0.4, -80.0);
+    assert_eq!(slide.alpha, (0.4, 0.4));
+    assert_eq!(slide.offset_x, (-80.0, offstage));
+}
+
+#[test]
+fn test_parse_scene() {
+    assert_eq!(
+        parse_scene("Bridge").unwrap(),
+        ("Bridge", BackgroundTransition::Dissolve, 0.5)
+    );
+    assert_eq!(
+        parse_scene("Bridge Wipe-Left").unwrap(),
+        ("Bridge", BackgroundTransition::Wipe(Direction::Left), 0.75)
+    );
+    assert_eq!(
+        parse_scene("Bridge rule=clock 1.5s").unwrap(),
+        (
+            "Bridge",
+            BackgroundTransition::Rule("clock".to_owned()),
+            1.5
+        )
+    );
+    assert_eq!(
+        parse_scene("Bridge Rule=Clock").unwrap().1,
+        BackgroundTransition::Rule("Clock".to_owned())
+    );
+    assert_eq!(
+        parse_scene("Bridge 2").unwrap(),
+        ("Bridge", BackgroundTransition::Dissolve, 2.0)
+    );
+    assert!(parse_scene("Bridge wipe-diagonal").is_err());
+    assert!(parse_scene("Bridge fade push-up").is_err());
+    assert!(parse_scene("Bridge -1s").is_err());
+    assert!(parse_scene("Bridge nan").is_err());
+    assert!(parse_scene("Bridge inf").is_err());
+
+    assert_eq!(rule_alpha(0.0, 0.0), 0.0);
+    assert_eq!(rule_alpha(1.0, 1.0), 1.0);
+    assert_eq!(rule_alpha(0.5, 0.25), 0.0);
+    assert_eq!(rule_alpha(0.0, 0.5), 1.0);
+}